@@ -0,0 +1,66 @@
+//! Times Lox programs that exercise the interpreter's hot paths, printing the median and
+//! range of several runs of each. Run with `cargo bench`, optionally followed by the names
+//! of the programs to run.
+
+use std::time::{Duration, Instant};
+
+use loxide::Loxide;
+
+const RUNS: usize = 7;
+
+const PROGRAMS: &[(&str, &str)] = &[
+    // A loop condition with an invariant sub-expression, and the same loop with it hoisted
+    // by hand, which bounds what hoisting could save
+    (
+        "invariant",
+        "var n = 1000; var i = 0; while (i < n * 2 + n * 3 + 1000000) i = i + 1;",
+    ),
+    (
+        "hoisted",
+        "var n = 1000; var limit = n * 2 + n * 3 + 1000000; var i = 0; while (i < limit) i = i + 1;",
+    ),
+    // Global variable reads and writes in a tight loop
+    (
+        "globals",
+        "var i = 0; var sum = 0; while (i < 1000000) { sum = sum + i; i = i + 1; }",
+    ),
+    // Local variable reads, writes and definitions in a hot loop
+    (
+        "locals",
+        "fn run() { var sum = 0; for (var i = 0; i < 1000000; i = i + 1) { var x = i; sum = sum + x; } return sum; } run();",
+    ),
+    // Calls through a global function
+    (
+        "fib",
+        "fn fib(n) { if (n < 2) return n; return fib(n - 1) + fib(n - 2); } fib(25);",
+    ),
+];
+
+fn main() {
+    let filters = std::env::args()
+        .skip(1)
+        .filter(|arg| !arg.starts_with("--"))
+        .collect::<Vec<_>>();
+
+    for (name, source) in PROGRAMS {
+        if !filters.is_empty() && !filters.iter().any(|filter| name.contains(filter.as_str())) {
+            continue;
+        }
+
+        let mut times = (0..RUNS).map(|_| time(source)).collect::<Vec<_>>();
+        times.sort();
+        println!(
+            "{name:>10}: median {:>8.1?} (min {:.1?}, max {:.1?})",
+            times[RUNS / 2],
+            times[0],
+            times[RUNS - 1]
+        );
+    }
+}
+
+fn time(source: &str) -> Duration {
+    let mut loxide = Loxide::new();
+    let start = Instant::now();
+    loxide.eval(source).expect("Benchmark programs should run");
+    start.elapsed()
+}
@@ -0,0 +1,20 @@
+//! A tree-walking interpreter for Lox.
+//!
+//! Most programs only need [`Loxide`], which runs source code end to end. The scanner,
+//! parser, and syntax tree are also public, for building tools on top of the language:
+//!
+//! ```
+//! use loxide::{Parser, Scanner, Stmt};
+//!
+//! let tokens = Scanner::new(b"print 1 + 2;".to_vec()).scan_tokens().unwrap();
+//! let statements = Parser::new(tokens).parse().unwrap();
+//! assert!(matches!(statements[0], Stmt::Print { .. }));
+//! ```
+
+mod loxide;
+
+pub use loxide::{
+    Arity, AuditReport, Callable, Clock, Diagnostic, Error, Expr, FunctionCaptures,
+    FunctionDeclaration, Interpreter, Literal, Loxide, NativeFunction, Parser, ParserError, Phase,
+    RuntimeError, Scanner, ScannerError, Stmt, Symbol, Token, TokenType, Value, Visitor,
+};
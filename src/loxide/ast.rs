@@ -1,62 +1,102 @@
-use std::fmt;
+use std::{
+    fmt,
+    hash::{Hash, Hasher},
+    rc::Rc,
+};
 
 use ordered_float::OrderedFloat;
+use serde::Serialize;
 
 use super::{interpreter::functions::FunctionDeclaration, token::Token};
 
-#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+#[derive(Clone, PartialEq, Eq, Hash, Debug, Serialize)]
 pub enum Expr {
     Binary {
         left: Box<Expr>,
-        operator: Token,
+        operator: Rc<Token>,
         right: Box<Expr>,
     },
     Grouping(Box<Expr>),
+    /// Evaluates each expression in turn, to the value of the last. Only parsed inside
+    /// parentheses, since commas already separate arguments and list elements.
+    Comma(Vec<Expr>),
     Literal(Literal),
+    ListLiteral(Vec<Expr>),
+    /// Key and value pairs, e.g. `{"a": 1, "b": 2}`, where `brace` is the opening brace.
+    MapLiteral {
+        brace: Rc<Token>,
+        entries: Vec<(Expr, Expr)>,
+    },
     Unary {
-        operator: Token,
+        operator: Rc<Token>,
         right: Box<Expr>,
     },
-    Variable(Token),
+    Variable(Rc<Token>),
     Assign {
-        name: Token,
+        name: Rc<Token>,
         value: Box<Expr>,
     },
     Logical {
         left: Box<Expr>,
-        operator: Token,
+        operator: Rc<Token>,
         right: Box<Expr>,
     },
+    Ternary {
+        condition: Box<Expr>,
+        then_branch: Box<Expr>,
+        else_branch: Box<Expr>,
+    },
     Call {
         callee: Box<Expr>,
-        paren: Token,
+        paren: Rc<Token>,
         arguments: Vec<Expr>,
     },
     Lambda(FunctionDeclaration),
+    /// Runs its body until a `break`, evaluating to the value given to the `break`, or nil.
+    Loop(Vec<Stmt>),
     Get {
         object: Box<Expr>,
-        name: Token,
+        name: Rc<Token>,
     },
     Set {
         object: Box<Expr>,
-        name: Token,
+        name: Rc<Token>,
+        value: Box<Expr>,
+    },
+    Index {
+        object: Box<Expr>,
+        index: Box<Expr>,
+        bracket: Rc<Token>,
+    },
+    SetIndex {
+        object: Box<Expr>,
+        index: Box<Expr>,
+        bracket: Rc<Token>,
         value: Box<Expr>,
     },
-    This(Token),
+    This(Rc<Token>),
     Super {
-        keyword: Token,
-        method: Token,
+        keyword: Rc<Token>,
+        method: Rc<Token>,
     },
 }
 
-#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+#[derive(Clone, PartialEq, Eq, Hash, Debug, Serialize)]
 pub enum Stmt {
     Expression(Expr),
-    Print(Expr),
+    Print {
+        keyword: Rc<Token>,
+        value: Expr,
+    },
     Var {
-        name: Token,
+        name: Rc<Token>,
         initializer: Option<Expr>,
     },
+    /// A variable that can't be assigned to after its declaration
+    Const {
+        name: Rc<Token>,
+        initializer: Expr,
+    },
     Block(Vec<Stmt>),
     If {
         condition: Expr,
@@ -66,21 +106,73 @@ pub enum Stmt {
     While {
         condition: Expr,
         body: Box<Stmt>,
+        /// The increment clause of a desugared `for` loop, run after every iteration
+        increment: Option<Expr>,
+        /// Whether the loop runs in the scope of a `for` loop's variable declaration,
+        /// which is copied for each iteration so closures capture that iteration's value
+        fresh_bindings: bool,
+    },
+    /// Runs the body once per element of a list, or per character of a string,
+    /// with `name` bound in a fresh scope each iteration.
+    ForIn {
+        name: Rc<Token>,
+        iterable: Expr,
+        body: Box<Stmt>,
+    },
+    Break {
+        keyword: Rc<Token>,
+        value: Option<Expr>,
+    },
+    Continue {
+        keyword: Rc<Token>,
+    },
+    /// Cases don't fall through: only the body of the first matching case (or
+    /// the default) is run, and `break` applies to the enclosing loop, if any.
+    Switch {
+        keyword: Rc<Token>,
+        subject: Expr,
+        cases: Vec<(Expr, Vec<Stmt>)>,
+        default: Option<Vec<Stmt>>,
+    },
+    /// Runs the body of the first arm whose type the subject has, or the `else` branch.
+    /// The type names an arm can test for are those `Value::type_of` gives.
+    Match {
+        subject: Expr,
+        arms: Vec<MatchArm>,
+        else_branch: Option<Box<Stmt>>,
     },
-    Break,
     Function(FunctionDeclaration),
     Return {
-        keyword: Token,
+        keyword: Rc<Token>,
         value: Option<Expr>,
     },
     Class {
-        name: Token,
+        name: Rc<Token>,
         superclass: Option<Expr>,
         methods: Vec<FunctionDeclaration>,
+        /// Methods declared with a `class` modifier, called on the class itself
+        static_methods: Vec<FunctionDeclaration>,
+    },
+    /// A `//` comment, only parsed when the parser is given the source's comments so the
+    /// formatter can write them back. It does nothing when run.
+    Comment {
+        /// The text after the `//`
+        text: String,
+        /// Whether it ends the line of the statement before it, rather than having its own
+        trailing: bool,
     },
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+/// An arm of a `match` statement, e.g. `Number n => print n + 1;`. The optional binding
+/// holds the subject in a scope around the body.
+#[derive(Clone, PartialEq, Eq, Hash, Debug, Serialize)]
+pub struct MatchArm {
+    pub type_name: Rc<Token>,
+    pub binding: Option<Rc<Token>>,
+    pub body: Stmt,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize)]
 pub enum Literal {
     Nil,
     Number(OrderedFloat<f64>),
@@ -88,6 +180,33 @@ pub enum Literal {
     String(String),
 }
 
+/// Identifies a variable, `this`, or `super` expression by its token. Every node has its own
+/// token, so identical expressions in different places (e.g. on separate REPL lines) stay
+/// distinct, which comparing the expressions themselves wouldn't guarantee. Holding the token
+/// keeps its address from being reused by a later node.
+#[derive(Clone, Debug)]
+pub struct NodeId(Rc<Token>);
+
+impl NodeId {
+    pub fn new(token: &Rc<Token>) -> Self {
+        Self(token.clone())
+    }
+}
+
+impl PartialEq for NodeId {
+    fn eq(&self, other: &Self) -> bool {
+        Rc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl Eq for NodeId {}
+
+impl Hash for NodeId {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        Rc::as_ptr(&self.0).hash(state);
+    }
+}
+
 pub trait Visitor<E, S> {
     fn visit_expr(&mut self, expr: &Expr) -> E;
     fn visit_stmt(&mut self, stmt: &Stmt) -> S;
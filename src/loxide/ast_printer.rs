@@ -0,0 +1,332 @@
+use super::{
+    ast::{Expr, Literal, Stmt, Visitor},
+    interpreter::functions::FunctionDeclaration,
+};
+
+/// Renders the AST as s-expressions, e.g. `(print (+ 1 (group (* 2 3))))`.
+pub struct AstPrinter;
+
+impl AstPrinter {
+    pub fn print(&mut self, statements: &[Stmt]) -> String {
+        statements
+            .iter()
+            .map(|stmt| self.visit_stmt(stmt) + "\n")
+            .collect()
+    }
+
+    fn statements(&mut self, statements: &[Stmt]) -> Vec<String> {
+        statements
+            .iter()
+            .map(|stmt| self.visit_stmt(stmt))
+            .collect()
+    }
+
+    fn function(&mut self, declaration: &FunctionDeclaration) -> String {
+        if declaration.is_getter {
+            let mut parts = vec![declaration.name.get_lexeme()];
+            parts.extend(self.statements(&declaration.body));
+            return parenthesize("get", parts);
+        }
+
+        let params = declaration
+            .params
+            .iter()
+            .map(|param| param.get_lexeme())
+            .collect::<Vec<_>>()
+            .join(" ");
+
+        let mut parts = vec![declaration.name.get_lexeme(), format!("({params})")];
+        parts.extend(self.statements(&declaration.body));
+        parenthesize("fn", parts)
+    }
+}
+
+fn parenthesize(name: &str, parts: impl IntoIterator<Item = String>) -> String {
+    let mut result = format!("({name}");
+    for part in parts {
+        result.push(' ');
+        result.push_str(&part);
+    }
+    result.push(')');
+    result
+}
+
+impl Visitor<String, String> for AstPrinter {
+    fn visit_expr(&mut self, expr: &Expr) -> String {
+        match expr {
+            Expr::Binary {
+                left,
+                operator,
+                right,
+            }
+            | Expr::Logical {
+                left,
+                operator,
+                right,
+            } => parenthesize(
+                &operator.get_lexeme(),
+                [self.visit_expr(left), self.visit_expr(right)],
+            ),
+
+            Expr::Grouping(expr) => parenthesize("group", [self.visit_expr(expr)]),
+
+            Expr::Comma(exprs) => {
+                parenthesize("comma", exprs.iter().map(|expr| self.visit_expr(expr)))
+            }
+
+            Expr::Literal(Literal::String(s)) => format!("\"{s}\""),
+
+            Expr::Literal(literal) => literal.to_string(),
+
+            Expr::ListLiteral(elements) => parenthesize(
+                "list",
+                elements.iter().map(|element| self.visit_expr(element)),
+            ),
+
+            Expr::MapLiteral { entries, .. } => parenthesize(
+                "map",
+                (entries.iter())
+                    .flat_map(|(key, value)| [self.visit_expr(key), self.visit_expr(value)]),
+            ),
+
+            Expr::Unary { operator, right } => {
+                parenthesize(&operator.get_lexeme(), [self.visit_expr(right)])
+            }
+
+            Expr::Variable(name) | Expr::This(name) => name.get_lexeme(),
+
+            Expr::Assign { name, value } => {
+                parenthesize("=", [name.get_lexeme(), self.visit_expr(value)])
+            }
+
+            Expr::Ternary {
+                condition,
+                then_branch,
+                else_branch,
+            } => parenthesize(
+                "?:",
+                [
+                    self.visit_expr(condition),
+                    self.visit_expr(then_branch),
+                    self.visit_expr(else_branch),
+                ],
+            ),
+
+            Expr::Call {
+                callee, arguments, ..
+            } => {
+                let mut parts = vec![self.visit_expr(callee)];
+                parts.extend(arguments.iter().map(|argument| self.visit_expr(argument)));
+                parenthesize("call", parts)
+            }
+
+            Expr::Lambda(declaration) => self.function(declaration),
+
+            Expr::Loop(body) => parenthesize("loop", self.statements(body)),
+
+            Expr::Get { object, name } => {
+                parenthesize(".", [self.visit_expr(object), name.get_lexeme()])
+            }
+
+            Expr::Set {
+                object,
+                name,
+                value,
+            } => parenthesize(
+                "=",
+                [
+                    parenthesize(".", [self.visit_expr(object), name.get_lexeme()]),
+                    self.visit_expr(value),
+                ],
+            ),
+
+            Expr::Index { object, index, .. } => {
+                parenthesize("[]", [self.visit_expr(object), self.visit_expr(index)])
+            }
+
+            Expr::SetIndex {
+                object,
+                index,
+                value,
+                ..
+            } => parenthesize(
+                "=",
+                [
+                    parenthesize("[]", [self.visit_expr(object), self.visit_expr(index)]),
+                    self.visit_expr(value),
+                ],
+            ),
+
+            Expr::Super { method, .. } => parenthesize("super", [method.get_lexeme()]),
+        }
+    }
+
+    fn visit_stmt(&mut self, stmt: &Stmt) -> String {
+        match stmt {
+            Stmt::Expression(expr) => parenthesize("expr", [self.visit_expr(expr)]),
+
+            Stmt::Print { value, .. } => parenthesize("print", [self.visit_expr(value)]),
+
+            Stmt::Var { name, initializer } => {
+                let mut parts = vec![name.get_lexeme()];
+                parts.extend(initializer.iter().map(|expr| self.visit_expr(expr)));
+                parenthesize("var", parts)
+            }
+
+            Stmt::Const { name, initializer } => {
+                parenthesize("const", [name.get_lexeme(), self.visit_expr(initializer)])
+            }
+
+            Stmt::Block(statements) => parenthesize("block", self.statements(statements)),
+
+            Stmt::If {
+                condition,
+                then_branch,
+                else_branch,
+            } => {
+                let mut parts = vec![self.visit_expr(condition), self.visit_stmt(then_branch)];
+                parts.extend(else_branch.iter().map(|stmt| self.visit_stmt(stmt)));
+                parenthesize("if", parts)
+            }
+
+            Stmt::While {
+                condition,
+                body,
+                increment,
+                ..
+            } => {
+                let mut parts = vec![self.visit_expr(condition), self.visit_stmt(body)];
+                parts.extend(increment.iter().map(|expr| self.visit_expr(expr)));
+                parenthesize("while", parts)
+            }
+
+            Stmt::ForIn {
+                name,
+                iterable,
+                body,
+            } => parenthesize(
+                "for-in",
+                [
+                    name.get_lexeme(),
+                    self.visit_expr(iterable),
+                    self.visit_stmt(body),
+                ],
+            ),
+
+            Stmt::Break { value, .. } => {
+                parenthesize("break", value.iter().map(|expr| self.visit_expr(expr)))
+            }
+
+            Stmt::Continue { .. } => parenthesize("continue", []),
+
+            Stmt::Switch {
+                subject,
+                cases,
+                default,
+                ..
+            } => {
+                let mut parts = vec![self.visit_expr(subject)];
+                for (value, body) in cases {
+                    let mut case = vec![self.visit_expr(value)];
+                    case.extend(self.statements(body));
+                    parts.push(parenthesize("case", case));
+                }
+                if let Some(body) = default {
+                    parts.push(parenthesize("default", self.statements(body)));
+                }
+                parenthesize("switch", parts)
+            }
+
+            Stmt::Match {
+                subject,
+                arms,
+                else_branch,
+            } => {
+                let mut parts = vec![self.visit_expr(subject)];
+                for arm in arms {
+                    let mut pattern = vec![arm.type_name.get_lexeme()];
+                    pattern.extend(arm.binding.iter().map(|binding| binding.get_lexeme()));
+                    pattern.push(self.visit_stmt(&arm.body));
+                    parts.push(parenthesize("arm", pattern));
+                }
+                if let Some(body) = else_branch {
+                    parts.push(parenthesize("else", [self.visit_stmt(body)]));
+                }
+                parenthesize("match", parts)
+            }
+
+            Stmt::Function(declaration) => self.function(declaration),
+
+            Stmt::Return { value, .. } => {
+                parenthesize("return", value.iter().map(|expr| self.visit_expr(expr)))
+            }
+
+            Stmt::Class {
+                name,
+                superclass,
+                methods,
+                static_methods,
+            } => {
+                let mut parts = vec![name.get_lexeme()];
+                if let Some(superclass) = superclass {
+                    parts.push(parenthesize("<", [self.visit_expr(superclass)]));
+                }
+                parts.extend(methods.iter().map(|method| self.function(method)));
+                parts.extend(
+                    (static_methods.iter())
+                        .map(|method| parenthesize("class", [self.function(method)])),
+                );
+                parenthesize("class", parts)
+            }
+
+            Stmt::Comment { text, .. } => parenthesize("comment", [format!("\"{text}\"")]),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::loxide::{test_support::session, Error};
+
+    #[test]
+    fn every_statement_and_expression_prints_as_an_s_expression() {
+        let source = r#"
+            class A < B { init(x) { this.x = x; } size { return super.size; } class make() {} }
+            fn f(a, b) { if (a and !b) return a; else return nil; }
+            var g = fn (x) { return x ? 1 : 2; };
+            const c = [1, "s", true][0];
+            var m = {"k": nil};
+            m["k"] = (g(1), c);
+            while (c < 3) { break; }
+            for (x in [1]) continue;
+            var v = loop { break 1; };
+            switch (v) { case 1: print v; default: print 0; }
+            match v { Number n => print n; else => print v; }
+            a.b = -a.b;
+        "#;
+        let expected = [
+            "(class A (< B) (fn init (x) (expr (= (. this x) x))) \
+             (get size (return (super size))) (class (fn make ())))",
+            "(fn f (a b) (if (and a (! b)) (return a) (return nil)))",
+            "(var g (fn <anonymous> (x) (return (?: x 1 2))))",
+            r#"(const c ([] (list 1 "s" true) 0))"#,
+            r#"(var m (map "k" nil))"#,
+            r#"(expr (= ([] m "k") (group (comma (call g 1) c))))"#,
+            "(while (< c 3) (block (break)))",
+            "(for-in x (list 1) (continue))",
+            "(var v (loop (break 1)))",
+            "(switch v (case 1 (print v)) (default (print 0)))",
+            "(match v (arm Number n (print n)) (else (print v)))",
+            "(expr (= (. a b) (- (. a b))))",
+        ];
+
+        let (loxide, _) = session();
+        let printed = loxide.dump_ast(source.as_bytes()).unwrap();
+        assert_eq!(printed.lines().collect::<Vec<_>>(), expected);
+
+        assert!(matches!(
+            loxide.dump_ast(b"print (1;"),
+            Err(Error::Parser(_))
+        ));
+    }
+}
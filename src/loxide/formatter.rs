@@ -0,0 +1,523 @@
+use super::{
+    ast::{Expr, Literal, Stmt, Visitor},
+    interpreter::functions::FunctionDeclaration,
+};
+
+/// Renders the AST back into canonical Lox source, with four-space indentation.
+///
+/// The AST doesn't keep the original layout, but a parser given the source's comments
+/// keeps them as statements, which are written back on their own lines or at the end of
+/// the line before. `for` loops are recognized from the `while` loops they're desugared
+/// into.
+pub struct Formatter {
+    indent: usize,
+}
+
+impl Formatter {
+    pub fn new() -> Self {
+        Self { indent: 0 }
+    }
+
+    pub fn format(&mut self, statements: &[Stmt]) -> String {
+        let mut result = String::new();
+        for (i, stmt) in statements.iter().enumerate() {
+            let line = self.visit_stmt(stmt);
+            if let Stmt::Comment { trailing: true, .. } = stmt {
+                end_line_with(&mut result, &line);
+                continue;
+            }
+
+            if needs_blank_line(statements, i) {
+                result.push('\n');
+            }
+            result.push_str(&line);
+            result.push('\n');
+        }
+        result
+    }
+
+    /// Renders statements one per line at the next indentation level, without the braces.
+    fn lines(&mut self, statements: &[Stmt]) -> String {
+        self.indent += 1;
+        let mut lines = String::new();
+        for stmt in statements {
+            let line = self.visit_stmt(stmt);
+            if let Stmt::Comment { trailing: true, .. } = stmt {
+                end_line_with(&mut lines, &line);
+            } else {
+                lines.push_str(&format!("{}{line}\n", self.padding()));
+            }
+        }
+        self.indent -= 1;
+        lines
+    }
+
+    fn block(&mut self, statements: &[Stmt]) -> String {
+        if statements.is_empty() {
+            return String::from("{}");
+        }
+        format!("{{\n{}{}}}", self.lines(statements), self.padding())
+    }
+
+    /// Renders the body of a control flow statement, following its header on the same line.
+    fn body(&mut self, stmt: &Stmt) -> String {
+        format!(" {}", self.visit_stmt(stmt))
+    }
+
+    fn padding(&self) -> String {
+        "    ".repeat(self.indent)
+    }
+
+    fn function(&mut self, declaration: &FunctionDeclaration) -> String {
+        let body = self.block(&declaration.body);
+        if declaration.is_getter {
+            return format!("{} {body}", declaration.name.get_lexeme());
+        }
+
+        let params = declaration
+            .params
+            .iter()
+            .map(|param| param.get_lexeme())
+            .collect::<Vec<_>>()
+            .join(", ");
+        format!("{}({params}) {body}", declaration.name.get_lexeme())
+    }
+
+    fn expressions(&mut self, exprs: &[Expr]) -> String {
+        exprs
+            .iter()
+            .map(|expr| self.visit_expr(expr))
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+
+    fn for_loop(
+        &mut self,
+        initializer: Option<&Stmt>,
+        condition: &Expr,
+        increment: Option<&Expr>,
+        body: &Stmt,
+    ) -> String {
+        let initializer = match initializer {
+            Some(stmt) => self.visit_stmt(stmt),
+            None => String::from(";"),
+        };
+        // An omitted condition is parsed as `true`
+        let condition = match condition {
+            Expr::Literal(Literal::Bool(true)) => String::new(),
+            condition => format!(" {}", self.visit_expr(condition)),
+        };
+        let increment = match increment {
+            Some(expr) => format!(" {}", self.visit_expr(expr)),
+            None => String::new(),
+        };
+        format!(
+            "for ({initializer}{condition};{increment}){}",
+            self.body(body)
+        )
+    }
+}
+
+impl Default for Formatter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn is_declaration(stmt: &Stmt) -> bool {
+    matches!(stmt, Stmt::Function(_) | Stmt::Class { .. })
+}
+
+/// Whether a blank line goes before the statement at `i`, which separates declarations
+/// from their neighbours. Comments on their own lines go with the statement after them,
+/// and trailing comments with the one before.
+fn needs_blank_line(statements: &[Stmt], i: usize) -> bool {
+    let previous = (statements[..i].iter().rev())
+        .find(|stmt| !matches!(stmt, Stmt::Comment { trailing: true, .. }));
+    let next = (statements[i..].iter()).find(|stmt| !matches!(stmt, Stmt::Comment { .. }));
+    match previous {
+        None | Some(Stmt::Comment { .. }) => false,
+        Some(previous) => is_declaration(previous) || next.is_some_and(is_declaration),
+    }
+}
+
+/// Appends a trailing comment to the last line rendered.
+fn end_line_with(lines: &mut String, comment: &str) {
+    if lines.ends_with('\n') {
+        lines.pop();
+    }
+    lines.push_str(&format!(" {comment}\n"));
+}
+
+impl Visitor<String, String> for Formatter {
+    fn visit_expr(&mut self, expr: &Expr) -> String {
+        match expr {
+            Expr::Binary {
+                left,
+                operator,
+                right,
+            }
+            | Expr::Logical {
+                left,
+                operator,
+                right,
+            } => format!(
+                "{} {} {}",
+                self.visit_expr(left),
+                operator.get_lexeme(),
+                self.visit_expr(right)
+            ),
+
+            Expr::Grouping(expr) => format!("({})", self.visit_expr(expr)),
+
+            Expr::Comma(exprs) => self.expressions(exprs),
+
+            Expr::Literal(Literal::String(s)) => format!("\"{s}\""),
+
+            Expr::Literal(literal) => literal.to_string(),
+
+            Expr::ListLiteral(elements) => format!("[{}]", self.expressions(elements)),
+
+            Expr::MapLiteral { entries, .. } => {
+                let entries = (entries.iter())
+                    .map(|(key, value)| {
+                        format!("{}: {}", self.visit_expr(key), self.visit_expr(value))
+                    })
+                    .collect::<Vec<_>>();
+                format!("{{{}}}", entries.join(", "))
+            }
+
+            Expr::Unary { operator, right } => {
+                format!("{}{}", operator.get_lexeme(), self.visit_expr(right))
+            }
+
+            Expr::Variable(name) | Expr::This(name) => name.get_lexeme(),
+
+            Expr::Assign { name, value } => {
+                format!("{} = {}", name.get_lexeme(), self.visit_expr(value))
+            }
+
+            Expr::Ternary {
+                condition,
+                then_branch,
+                else_branch,
+            } => format!(
+                "{} ? {} : {}",
+                self.visit_expr(condition),
+                self.visit_expr(then_branch),
+                self.visit_expr(else_branch)
+            ),
+
+            Expr::Call {
+                callee, arguments, ..
+            } => format!(
+                "{}({})",
+                self.visit_expr(callee),
+                self.expressions(arguments)
+            ),
+
+            Expr::Lambda(declaration) => {
+                let params = declaration
+                    .params
+                    .iter()
+                    .map(|param| param.get_lexeme())
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                format!("fn ({params}) {}", self.block(&declaration.body))
+            }
+
+            Expr::Loop(body) => format!("loop {}", self.block(body)),
+
+            Expr::Get { object, name } => {
+                format!("{}.{}", self.visit_expr(object), name.get_lexeme())
+            }
+
+            Expr::Set {
+                object,
+                name,
+                value,
+            } => format!(
+                "{}.{} = {}",
+                self.visit_expr(object),
+                name.get_lexeme(),
+                self.visit_expr(value)
+            ),
+
+            Expr::Index { object, index, .. } => {
+                format!("{}[{}]", self.visit_expr(object), self.visit_expr(index))
+            }
+
+            Expr::SetIndex {
+                object,
+                index,
+                value,
+                ..
+            } => format!(
+                "{}[{}] = {}",
+                self.visit_expr(object),
+                self.visit_expr(index),
+                self.visit_expr(value)
+            ),
+
+            Expr::Super { method, .. } => format!("super.{}", method.get_lexeme()),
+        }
+    }
+
+    fn visit_stmt(&mut self, stmt: &Stmt) -> String {
+        match stmt {
+            // A loop in statement position doesn't need a trailing semicolon
+            Stmt::Expression(expr @ Expr::Loop(_)) => self.visit_expr(expr),
+
+            Stmt::Expression(expr) => format!("{};", self.visit_expr(expr)),
+
+            Stmt::Print { value, .. } => format!("print {};", self.visit_expr(value)),
+
+            Stmt::Var { name, initializer } => match initializer {
+                Some(expr) => format!("var {} = {};", name.get_lexeme(), self.visit_expr(expr)),
+                None => format!("var {};", name.get_lexeme()),
+            },
+
+            Stmt::Const { name, initializer } => {
+                format!(
+                    "const {} = {};",
+                    name.get_lexeme(),
+                    self.visit_expr(initializer)
+                )
+            }
+
+            // A `for` loop declaring a variable is desugared into a block around a `while`
+            // that copies the variable each iteration. Any other block is written as one.
+            Stmt::Block(statements) => match statements.as_slice() {
+                [initializer @ Stmt::Var { .. }, Stmt::While {
+                    condition,
+                    body,
+                    increment,
+                    fresh_bindings,
+                }] if *fresh_bindings => {
+                    self.for_loop(Some(initializer), condition, increment.as_ref(), body)
+                }
+                statements => self.block(statements),
+            },
+
+            Stmt::If {
+                condition,
+                then_branch,
+                else_branch,
+            } => {
+                let mut result = format!(
+                    "if ({}){}",
+                    self.visit_expr(condition),
+                    self.body(then_branch)
+                );
+                if let Some(else_branch) = else_branch {
+                    // `else` follows a closing brace, or starts its own line
+                    if matches!(**then_branch, Stmt::Block(_)) {
+                        result.push_str(" else");
+                    } else {
+                        result.push_str(&format!("\n{}else", self.padding()));
+                    }
+                    result.push_str(&self.body(else_branch));
+                }
+                result
+            }
+
+            Stmt::While {
+                condition,
+                body,
+                increment: Some(increment),
+                ..
+            } => self.for_loop(None, condition, Some(increment), body),
+
+            Stmt::While {
+                condition, body, ..
+            } => format!("while ({}){}", self.visit_expr(condition), self.body(body)),
+
+            Stmt::ForIn {
+                name,
+                iterable,
+                body,
+            } => format!(
+                "for ({} in {}){}",
+                name.get_lexeme(),
+                self.visit_expr(iterable),
+                self.body(body)
+            ),
+
+            Stmt::Break { value, .. } => match value {
+                Some(expr) => format!("break {};", self.visit_expr(expr)),
+                None => String::from("break;"),
+            },
+
+            Stmt::Continue { .. } => String::from("continue;"),
+
+            Stmt::Switch {
+                subject,
+                cases,
+                default,
+                ..
+            } => {
+                let mut result = format!("switch ({}) {{\n", self.visit_expr(subject));
+                self.indent += 1;
+                for (value, body) in cases {
+                    let value = self.visit_expr(value);
+                    result.push_str(&format!("{}case {value}:\n", self.padding()));
+                    result.push_str(&self.lines(body));
+                }
+                if let Some(body) = default {
+                    result.push_str(&format!("{}default:\n", self.padding()));
+                    result.push_str(&self.lines(body));
+                }
+                self.indent -= 1;
+                result.push_str(&format!("{}}}", self.padding()));
+                result
+            }
+
+            Stmt::Match {
+                subject,
+                arms,
+                else_branch,
+            } => {
+                let mut result = format!("match {} {{\n", self.visit_expr(subject));
+                self.indent += 1;
+                for arm in arms {
+                    let mut pattern = arm.type_name.get_lexeme();
+                    if let Some(binding) = &arm.binding {
+                        pattern.push_str(&format!(" {}", binding.get_lexeme()));
+                    }
+                    let body = self.visit_stmt(&arm.body);
+                    result.push_str(&format!("{}{pattern} => {body}\n", self.padding()));
+                }
+                if let Some(body) = else_branch {
+                    let body = self.visit_stmt(body);
+                    result.push_str(&format!("{}else => {body}\n", self.padding()));
+                }
+                self.indent -= 1;
+                result.push_str(&format!("{}}}", self.padding()));
+                result
+            }
+
+            Stmt::Function(declaration) => format!("fn {}", self.function(declaration)),
+
+            Stmt::Return { value, .. } => match value {
+                Some(expr) => format!("return {};", self.visit_expr(expr)),
+                None => String::from("return;"),
+            },
+
+            Stmt::Class {
+                name,
+                superclass,
+                methods,
+                static_methods,
+            } => {
+                let mut result = format!("class {}", name.get_lexeme());
+                if let Some(superclass) = superclass {
+                    result.push_str(&format!(" < {}", self.visit_expr(superclass)));
+                }
+                if methods.is_empty() && static_methods.is_empty() {
+                    return result + " {}";
+                }
+
+                self.indent += 1;
+                let mut lines = Vec::new();
+                let methods = (methods.iter().map(|method| ("", method)))
+                    .chain(static_methods.iter().map(|method| ("class ", method)));
+                for (modifier, method) in methods {
+                    let mut line = String::new();
+                    for comment in &method.comments {
+                        line.push_str(&format!("{}//{comment}\n", self.padding()));
+                    }
+                    let function = self.function(method);
+                    line.push_str(&format!("{}{modifier}{function}\n", self.padding()));
+                    lines.push(line);
+                }
+                self.indent -= 1;
+
+                // Methods are separated by blank lines
+                format!("{result} {{\n{}{}}}", lines.join("\n"), self.padding())
+            }
+
+            Stmt::Comment { text, .. } => format!("//{text}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::loxide::test_support::{output, session};
+
+    /// A program using every kind of statement, and the expressions with their own syntax
+    const PROGRAM: &str = r#"
+        // A comment on its own line
+        const LIMIT = 3; // and one after a statement
+        class Shape { init(w) { this.w = w; } area { return this.w * this.w; }
+            class unit() { return Shape(1); } }
+        class Square < Shape { init(w) { super.init(w); }
+            describe() { return "square " + to_string(this.area); } }
+        fn classify(x) { match x { Number n => return n > 0 ? "positive" : "other";
+            String => return "text"; else => return "unknown"; } }
+        var total = 0; var seen = 0;
+        for (var i = 0; i < LIMIT; i = i + 1) { if (i == 1) continue; total = total + i; }
+        for (x in [1, 2]) print x;
+        for (;;) break;
+        while (total < 10) total = total + 4;
+        switch (total) { case 10: print "ten"; case 12: print "twelve"; default: print "other"; }
+        var found = loop { seen = seen + 1; if (seen > 2) break seen; };
+        var f = fn (a, b) {
+            // Inside a function
+            return a ** b;
+        };
+        print (1, 2);
+        print {"a": [1, 2], "b": !true};
+        print Square(2).describe(); print Shape.unit().area;
+        print classify(-1); print f(2, 3);
+        print found; print -total & 3 | 4 ^ 1 << 1;
+        if (total > 1) { print "big"; }
+        else if (total) print "small"; else print "none";
+        { var y = nil; y = total div 3; print y; }
+    "#;
+
+    #[test]
+    fn formatting_twice_is_the_same_as_once_and_keeps_behaviour() {
+        let format = |source: &str| session().0.format(source.as_bytes()).unwrap();
+        let formatted = format(PROGRAM);
+        assert_eq!(format(&formatted), formatted);
+        assert_eq!(output(&formatted), output(PROGRAM));
+    }
+
+    #[test]
+    fn comments_are_kept_next_to_what_they_were_next_to() {
+        let source = "
+            // Adds two numbers
+            fn add(a, b) {
+                return a + b; // the sum
+                // before the brace
+            }
+            var list = [1, // inside a statement
+                2];
+            a(); b(); // after the second
+            class A {
+                // the constructor
+                init() {}
+            }
+        ";
+        let expected = [
+            "// Adds two numbers",
+            "fn add(a, b) {",
+            "    return a + b; // the sum",
+            "    // before the brace",
+            "}",
+            "",
+            "// inside a statement",
+            "var list = [1, 2];",
+            "a();",
+            "b(); // after the second",
+            "",
+            "class A {",
+            "    // the constructor",
+            "    init() {}",
+            "}",
+        ];
+        let formatted = session().0.format(source.as_bytes()).unwrap();
+        assert_eq!(formatted.lines().collect::<Vec<_>>(), expected);
+    }
+}
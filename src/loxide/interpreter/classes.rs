@@ -1,17 +1,34 @@
-use std::{cell::RefCell, collections::HashMap, fmt, rc::Rc};
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    fmt,
+    hash::{Hash, Hasher},
+    rc::Rc,
+};
 
 use crate::loxide::token::Token;
 
-use super::{functions::Callable, value::Value, Error, Interpreter, Result};
+use super::{
+    functions::{Arity, Callable},
+    value::Value,
+    Error, Interpreter, Result,
+};
 
 #[derive(Clone)]
 pub struct Class {
     pub name: String,
     pub superclass: Option<Box<Value>>,
-    pub methods: HashMap<String, Value>,
+    /// Shared by every copy of the class, which is also what gives the class its identity
+    pub methods: Rc<HashMap<String, Value>>,
+    pub static_methods: HashMap<String, Value>,
 }
 
 impl Class {
+    /// Whether both handles refer to the same class, rather than two with the same name.
+    pub fn is(&self, other: &Class) -> bool {
+        self.name == other.name && Rc::ptr_eq(&self.methods, &other.methods)
+    }
+
     pub fn find_method(&self, name: &str) -> Option<Value> {
         if let Some(value) = self.methods.get(name) {
             Some(value.clone())
@@ -24,10 +41,28 @@ impl Class {
             None
         }
     }
+
+    /// Looks up a static method on the class, then on its superclasses.
+    ///
+    /// Static and instance methods are kept apart: `Class.name` only finds static methods,
+    /// and instances only find instance methods, so the two never shadow each other.
+    pub fn find_static_method(&self, name: &str) -> Option<Value> {
+        if let Some(value) = self.static_methods.get(name) {
+            Some(value.clone())
+        } else if let Some(superclass) = self.superclass.clone() {
+            match *superclass {
+                Value::Class(class) => class.find_static_method(name),
+                _ => unreachable!("Expected class for superclass"),
+            }
+        } else {
+            None
+        }
+    }
 }
 
-impl Callable for Class {
-    fn arity(&self) -> usize {
+// Implemented on the shared handle, so instances can keep a reference to their class
+impl Callable for Rc<Class> {
+    fn arity(&self) -> Arity {
         // If the class has an init method, return its arity
         if let Some(init) = self.find_method("init") {
             match init {
@@ -35,10 +70,14 @@ impl Callable for Class {
                 _ => unreachable!("Expected function for init method"),
             }
         } else {
-            0
+            Arity::Exact(0)
         }
     }
 
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
     fn call(&self, interpreter: &mut Interpreter, arguments: Vec<Value>) -> Result<Value> {
         let instance = Instance::new(self.clone());
         // Bind and call the init method if it exists
@@ -53,15 +92,15 @@ impl Callable for Class {
     }
 }
 
-impl TryFrom<Value> for Class {
+impl TryFrom<Value> for Rc<Class> {
     type Error = Error;
 
-    fn try_from(value: Value) -> Result<Class, Error> {
+    fn try_from(value: Value) -> Result<Rc<Class>, Error> {
         match value {
             Value::Class(class) => Ok(class),
             _ => Err(Error::ConversionError {
                 from: value,
-                to: "<class>".to_string(),
+                to: "Class".to_string(),
             }),
         }
     }
@@ -75,18 +114,27 @@ impl fmt::Debug for Class {
 
 #[derive(Clone)]
 pub struct Instance {
-    class: Class,
+    class: Rc<Class>,
     fields: Rc<RefCell<HashMap<String, Value>>>,
 }
 
 impl Instance {
-    pub fn new(class: Class) -> Self {
+    pub fn new(class: Rc<Class>) -> Self {
         Self {
             class,
             fields: Rc::new(RefCell::new(HashMap::new())),
         }
     }
 
+    pub fn class(&self) -> &Rc<Class> {
+        &self.class
+    }
+
+    /// Whether both handles refer to the same instance, rather than two with equal fields.
+    pub fn is(&self, other: &Instance) -> bool {
+        Rc::ptr_eq(&self.fields, &other.fields)
+    }
+
     pub fn get(&self, name: &Token) -> Option<Value> {
         if let Some(value) = self.fields.borrow().get(&name.get_lexeme()) {
             Some(value.clone())
@@ -103,6 +151,16 @@ impl Instance {
     pub fn set(&mut self, name: &Token, value: Value) {
         self.fields.borrow_mut().insert(name.get_lexeme(), value);
     }
+
+    /// Whether the instance has a field with this name. Methods aren't fields.
+    pub fn has_field(&self, name: &str) -> bool {
+        self.fields.borrow().contains_key(name)
+    }
+
+    /// Removes a field, returning whether there was one to remove.
+    pub fn delete_field(&mut self, name: &str) -> bool {
+        self.fields.borrow_mut().remove(name).is_some()
+    }
 }
 
 impl TryFrom<Value> for Instance {
@@ -113,12 +171,19 @@ impl TryFrom<Value> for Instance {
             Value::Instance(instance) => Ok(instance),
             _ => Err(Error::ConversionError {
                 from: value,
-                to: "<instance>".to_string(),
+                to: "Instance".to_string(),
             }),
         }
     }
 }
 
+/// Hashes the instance by identity, consistently with `is`.
+impl Hash for Instance {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        std::ptr::hash(Rc::as_ptr(&self.fields), state);
+    }
+}
+
 impl fmt::Debug for Instance {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f, "<instance of {}>", self.class.name)
@@ -1,5 +1,7 @@
 use std::{cell::RefCell, collections::HashMap, rc::Rc};
 
+use crate::loxide::symbol::Symbol;
+
 use super::value::Value;
 
 // Cactus stack / parent-pointer tree
@@ -9,10 +11,18 @@ pub struct Environment(Option<Rc<Scope>>);
 
 #[derive(Debug)]
 struct Scope {
-    variables: RefCell<HashMap<String, Value>>,
+    variables: RefCell<Variables>,
     enclosing: Environment,
 }
 
+/// Globals are looked up by name, since they can be defined after the code that uses them
+/// is resolved. Locals live in the slots the resolver assigned them, in declaration order.
+#[derive(Debug)]
+enum Variables {
+    Global(HashMap<Symbol, Value>),
+    Local(Vec<Value>),
+}
+
 impl Clone for Environment {
     fn clone(&self) -> Self {
         Self(self.0.clone())
@@ -22,18 +32,48 @@ impl Clone for Environment {
 impl Environment {
     /// Create a new global environment scope.
     pub fn global() -> Self {
-        Environment(None).nest()
+        let scope = Scope {
+            variables: RefCell::new(Variables::Global(HashMap::new())),
+            enclosing: Environment(None),
+        };
+        Self(Some(Rc::new(scope)))
     }
 
     /// Create a new nested environment scope.
     pub fn nest(&self) -> Self {
         let scope = Scope {
-            variables: RefCell::new(HashMap::new()),
+            variables: RefCell::new(Variables::Local(Vec::new())),
             enclosing: self.clone(),
         };
         Self(Some(Rc::new(scope)))
     }
 
+    /// Creates a new scope with the same enclosing scope and a copy of this scope's variables.
+    pub fn copy_scope(&self) -> Self {
+        match self.0.as_ref() {
+            Some(scope) => {
+                let variables = match &*scope.variables.borrow() {
+                    Variables::Global(variables) => Variables::Global(variables.clone()),
+                    Variables::Local(slots) => Variables::Local(slots.clone()),
+                };
+                Self(Some(Rc::new(Scope {
+                    variables: RefCell::new(variables),
+                    enclosing: scope.enclosing.clone(),
+                })))
+            }
+            None => Self(None),
+        }
+    }
+
+    /// Whether both handles refer to the same scope.
+    pub fn is(&self, other: &Environment) -> bool {
+        match (&self.0, &other.0) {
+            (Some(left), Some(right)) => Rc::ptr_eq(left, right),
+            (None, None) => true,
+            _ => false,
+        }
+    }
+
     pub fn enclosing(&self) -> Self {
         self.0.as_ref().map_or(Self(None), |s| s.enclosing.clone())
     }
@@ -42,46 +82,85 @@ impl Environment {
         (0..distance).fold(self.clone(), |env, _| env.enclosing())
     }
 
-    pub fn define(&mut self, name: String, value: Value) {
+    /// Defines a variable in this scope. Locals take the next slot, so they must be
+    /// defined in the same order the resolver declared them.
+    pub fn define(&mut self, name: Symbol, value: Value) {
         if let Some(scope) = self.0.as_ref() {
-            scope.variables.borrow_mut().insert(name, value);
+            match &mut *scope.variables.borrow_mut() {
+                Variables::Global(variables) => {
+                    variables.insert(name, value);
+                }
+                Variables::Local(slots) => slots.push(value),
+            }
+        }
+    }
+
+    /// The names defined in this scope, if it's global. Locals are stored by slot, so have none.
+    pub fn names(&self) -> Vec<Symbol> {
+        match self.0.as_ref() {
+            Some(scope) => match &*scope.variables.borrow() {
+                Variables::Global(variables) => variables.keys().copied().collect(),
+                Variables::Local(_) => Vec::new(),
+            },
+            None => Vec::new(),
         }
     }
 
-    pub fn lookup(&self, name: String) -> Option<Value> {
+    /// Looks up a global variable by name.
+    pub fn lookup(&self, name: Symbol) -> Option<Value> {
         if let Some(scope) = self.0.as_ref() {
-            // If the variable is not found in the current environment,
-            // we recursively search the enclosing environment.
-            scope
-                .variables
-                .borrow()
-                .get(&name)
-                .cloned()
-                .or_else(|| self.enclosing().lookup(name))
+            match &*scope.variables.borrow() {
+                Variables::Global(variables) => variables.get(&name).cloned(),
+                // Locals have no names, so keep searching towards the global scope
+                Variables::Local(_) => self.enclosing().lookup(name),
+            }
         } else {
             None
         }
     }
 
-    pub fn lookup_at(&self, distance: usize, name: String) -> Option<Value> {
-        self.ancestor(distance).lookup(name)
+    /// Looks up the local variable in a slot of the scope `distance` levels up.
+    pub fn lookup_at(&self, distance: usize, slot: usize) -> Option<Value> {
+        let environment = self.ancestor(distance);
+        let scope = environment.0.as_ref()?;
+        let variables = scope.variables.borrow();
+        match &*variables {
+            Variables::Local(slots) => slots.get(slot).cloned(),
+            Variables::Global(_) => None,
+        }
     }
 
-    pub fn assign(&mut self, name: String, value: Value) -> bool {
+    /// Assigns to an existing global variable by name.
+    pub fn assign(&mut self, name: Symbol, value: Value) -> bool {
         if let Some(scope) = self.0.as_ref() {
-            if scope.variables.borrow().contains_key(&name) {
-                scope.variables.borrow_mut().insert(name, value);
-                return true;
+            match &mut *scope.variables.borrow_mut() {
+                Variables::Global(variables) if variables.contains_key(&name) => {
+                    variables.insert(name, value);
+                    return true;
+                }
+                Variables::Global(_) => return false,
+                Variables::Local(_) => {}
             }
 
-            // If the variable is not found in the current environment,
-            // we recursively search the enclosing environment.
+            // Locals have no names, so keep searching towards the global scope
             return self.enclosing().assign(name, value);
         }
         false
     }
 
-    pub fn assign_at(&mut self, distance: usize, name: String, value: Value) -> bool {
-        self.ancestor(distance).assign(name, value)
+    /// Assigns to the local variable in a slot of the scope `distance` levels up.
+    pub fn assign_at(&mut self, distance: usize, slot: usize, value: Value) -> bool {
+        let environment = self.ancestor(distance);
+        let Some(scope) = environment.0.as_ref() else {
+            return false;
+        };
+        let mut variables = scope.variables.borrow_mut();
+        match &mut *variables {
+            Variables::Local(slots) if slot < slots.len() => {
+                slots[slot] = value;
+                true
+            }
+            _ => false,
+        }
     }
 }
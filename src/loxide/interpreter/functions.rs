@@ -1,6 +1,12 @@
-use std::fmt;
+use std::{
+    fmt,
+    hash::{Hash, Hasher},
+    rc::Rc,
+};
+
+use serde::Serialize;
 
-use crate::loxide::{ast::Stmt, token::Token};
+use crate::loxide::{ast::Stmt, symbol::Symbol, token::Token};
 
 use super::{
     classes::Instance, environment::Environment, value::Value, Error, Interpreter, Result,
@@ -8,21 +14,82 @@ use super::{
 
 pub trait Callable {
     fn call(&self, interpreter: &mut Interpreter, arguments: Vec<Value>) -> Result<Value>;
-    fn arity(&self) -> usize;
+    /// How many arguments the value can be called with, checked before `call`.
+    fn arity(&self) -> Arity;
+    /// The name shown for calls to this value in a call stack trace.
+    fn name(&self) -> String;
+}
+
+/// The number of arguments a callable accepts. Lox functions and classes always take an
+/// exact number, natives can also make trailing arguments optional or take any number.
+/// Missing optional arguments aren't filled in, so the function receives fewer of them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Arity {
+    Exact(usize),
+    /// Between the two bounds, inclusive
+    Range(usize, usize),
+    AtLeast(usize),
+}
+
+impl Arity {
+    pub fn accepts(&self, count: usize) -> bool {
+        match *self {
+            Self::Exact(n) => count == n,
+            Self::Range(min, max) => (min..=max).contains(&count),
+            Self::AtLeast(min) => count >= min,
+        }
+    }
 }
 
+impl From<usize> for Arity {
+    fn from(n: usize) -> Self {
+        Self::Exact(n)
+    }
+}
+
+impl fmt::Display for Arity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Exact(n) => write!(f, "{n}"),
+            Self::Range(min, max) => write!(f, "{min} to {max}"),
+            Self::AtLeast(min) => write!(f, "at least {min}"),
+        }
+    }
+}
+
+/// The Rust function behind a native, which may be a closure over state of the embedder.
+pub type NativeFn = dyn Fn(&mut Interpreter, Vec<Value>) -> Result<Value>;
+
 #[derive(Clone)]
 pub struct NativeFunction {
     pub name: String,
-    pub arity: usize,
-    pub function: fn(&mut Interpreter, Vec<Value>) -> Result<Value>,
+    pub arity: Arity,
+    pub function: Rc<NativeFn>,
+}
+
+impl NativeFunction {
+    pub fn new(
+        name: &str,
+        arity: impl Into<Arity>,
+        function: impl Fn(&mut Interpreter, Vec<Value>) -> Result<Value> + 'static,
+    ) -> Self {
+        Self {
+            name: name.to_string(),
+            arity: arity.into(),
+            function: Rc::new(function),
+        }
+    }
 }
 
 impl Callable for NativeFunction {
-    fn arity(&self) -> usize {
+    fn arity(&self) -> Arity {
         self.arity
     }
 
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
     fn call(&self, interpreter: &mut Interpreter, arguments: Vec<Value>) -> Result<Value> {
         (self.function)(interpreter, arguments)
     }
@@ -34,16 +101,21 @@ impl fmt::Debug for NativeFunction {
     }
 }
 
-#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+#[derive(Clone, PartialEq, Eq, Hash, Debug, Serialize)]
 pub struct FunctionDeclaration {
-    pub name: Token,
-    pub params: Vec<Token>,
+    pub name: Rc<Token>,
+    pub params: Vec<Rc<Token>>,
     pub body: Vec<Stmt>,
+    /// Whether this is a method declared without a parameter list, which runs on access
+    pub is_getter: bool,
+    /// The comments on the lines before a method, when the parser is given them
+    pub comments: Vec<String>,
 }
 
 #[derive(Clone)]
 pub struct Function {
-    pub declaration: FunctionDeclaration,
+    /// Shared, since every closure and bound method made from a declaration runs its body
+    pub declaration: Rc<FunctionDeclaration>,
     pub closure: Environment,
     pub is_init: bool,
 }
@@ -51,7 +123,7 @@ pub struct Function {
 impl Function {
     pub fn new(declaration: FunctionDeclaration, closure: Environment) -> Self {
         Self {
-            declaration,
+            declaration: Rc::new(declaration),
             closure,
             is_init: false,
         }
@@ -59,15 +131,27 @@ impl Function {
 
     pub fn new_init(declaration: FunctionDeclaration, closure: Environment) -> Self {
         Self {
-            declaration,
+            declaration: Rc::new(declaration),
             closure,
             is_init: true,
         }
     }
 
+    /// Whether both are the same function: declared by the same code, closing over the same
+    /// scope. Binding a method creates a new scope, so each access to a method is distinct.
+    pub fn is(&self, other: &Function) -> bool {
+        Rc::ptr_eq(&self.declaration.name, &other.declaration.name)
+            && self.closure.is(&other.closure)
+    }
+
+    /// The source line on which the function was declared.
+    pub fn line(&self) -> usize {
+        self.declaration.name.get_line()
+    }
+
     pub fn bind(self, instance: Instance) -> Self {
         let mut environment = self.closure.nest();
-        environment.define("this".to_string(), Value::Instance(instance));
+        environment.define(Symbol::intern("this"), Value::Instance(instance));
         Self {
             closure: environment,
             ..self
@@ -76,24 +160,41 @@ impl Function {
 }
 
 impl Callable for Function {
-    fn arity(&self) -> usize {
-        self.declaration.params.len()
+    fn arity(&self) -> Arity {
+        Arity::Exact(self.declaration.params.len())
+    }
+
+    fn name(&self) -> String {
+        self.declaration.name.get_lexeme()
     }
 
     fn call(&self, interpreter: &mut Interpreter, arguments: Vec<Value>) -> Result<Value> {
+        // Stop runaway recursion before it overflows the native stack
+        if interpreter.call_limit_reached() {
+            return Err(Error::StackOverflow {
+                limit: interpreter.depth,
+            });
+        }
+
         let mut environment = self.closure.nest();
 
         for (param, arg) in self.declaration.params.iter().zip(arguments) {
-            environment.define(param.get_lexeme(), arg);
+            environment.define(param.get_symbol(), arg);
         }
 
+        interpreter.depth += 1;
         let result = interpreter.execute_block(&self.declaration.body, environment);
+        interpreter.depth -= 1;
         if self.is_init {
-            // If this is an initializer, always return `this`
-            Ok(self
-                .closure
-                .lookup_at(0, "this".to_string())
-                .expect("Expected `this` to be defined in initializer"))
+            // If this is an initializer, return `this` unless it failed, even from a bare
+            // `return`
+            match result {
+                Ok(_) | Err(Error::Return(_)) => Ok(self
+                    .closure
+                    .lookup_at(0, 0)
+                    .expect("Expected `this` to be defined in initializer")),
+                Err(e) => Err(e),
+            }
         } else {
             // Otherwise, return the result of the block
             match result {
@@ -113,12 +214,20 @@ impl TryFrom<Value> for Function {
             Value::Function(func) => Ok(func),
             _ => Err(Error::ConversionError {
                 from: value,
-                to: "<fn>".to_string(),
+                to: "Function".to_string(),
             }),
         }
     }
 }
 
+/// Hashes the function by the code that declared it, so every function that `is` another
+/// hashes the same.
+impl Hash for Function {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        std::ptr::hash(Rc::as_ptr(&self.declaration.name), state);
+    }
+}
+
 impl fmt::Debug for Function {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f, "<fn `{}`>", self.declaration.name.get_lexeme())
@@ -0,0 +1,190 @@
+use std::{
+    cell::RefCell,
+    collections::{hash_map::DefaultHasher, HashMap},
+    fmt,
+    hash::{Hash, Hasher},
+    rc::Rc,
+};
+
+use crate::loxide::token::Token;
+
+use super::{functions::Function, value::Value, Error, Interpreter, Result};
+
+/// A map value, whose entries are kept in the order their keys were first inserted, so
+/// printing and iterating a map is deterministic. Setting an existing key keeps its place.
+///
+/// Instances whose class defines `__hash__` and `__eq__` methods are keyed by calling them,
+/// so two instances can find the same entry. Other instances are keyed by identity. Since
+/// those methods can run any code, the map is never borrowed while they do.
+#[derive(Clone, Default)]
+pub struct Map(Rc<RefCell<Entries>>);
+
+#[derive(Default)]
+struct Entries {
+    entries: Vec<(Value, Value)>,
+    /// The positions in `entries` of the keys with each hash
+    positions: HashMap<u64, Vec<usize>>,
+}
+
+impl Map {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Whether both handles refer to the same map, rather than two with equal entries.
+    pub fn is(&self, other: &Map) -> bool {
+        Rc::ptr_eq(&self.0, &other.0)
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.borrow().entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The key and value of each entry, in insertion order. They're copied out, so the map
+    /// can be changed while they're visited.
+    pub fn entries(&self) -> Vec<(Value, Value)> {
+        self.0.borrow().entries.clone()
+    }
+
+    /// The value for `key`, if it's in the map. `at` is the token that errors, and calls to
+    /// `__hash__` and `__eq__`, are reported at.
+    pub fn get(
+        &self,
+        key: &Value,
+        interpreter: &mut Interpreter,
+        at: &Rc<Token>,
+    ) -> Result<Option<Value>> {
+        let (_, position) = self.find(key, interpreter, at)?;
+        Ok(position.map(|i| self.0.borrow().entries[i].1.clone()))
+    }
+
+    /// Sets the value for `key`, adding it after every other key if it's not in the map.
+    pub fn insert(
+        &self,
+        key: Value,
+        value: Value,
+        interpreter: &mut Interpreter,
+        at: &Rc<Token>,
+    ) -> Result<()> {
+        let (hash, position) = self.find(&key, interpreter, at)?;
+        let mut map = self.0.borrow_mut();
+        match position {
+            Some(i) => map.entries[i].1 = value,
+            None => {
+                let i = map.entries.len();
+                map.positions.entry(hash).or_default().push(i);
+                map.entries.push((key, value));
+            }
+        }
+        Ok(())
+    }
+
+    /// Hashes `key`, and finds the position of its entry if there is one. Entries are never
+    /// removed, so the position stays valid after running Lox code.
+    fn find(
+        &self,
+        key: &Value,
+        interpreter: &mut Interpreter,
+        at: &Rc<Token>,
+    ) -> Result<(u64, Option<usize>)> {
+        let hash = hash_key(key, interpreter, at)?;
+        let candidates = {
+            let map = self.0.borrow();
+            let positions = map.positions.get(&hash).map_or(&[][..], Vec::as_slice);
+            (positions.iter())
+                .map(|&i| (i, map.entries[i].0.clone()))
+                .collect::<Vec<_>>()
+        };
+
+        for (i, candidate) in candidates {
+            if keys_equal(key, &candidate, interpreter, at)? {
+                return Ok((hash, Some(i)));
+            }
+        }
+        Ok((hash, None))
+    }
+}
+
+impl fmt::Debug for Map {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_map()
+            .entries(
+                self.0
+                    .borrow()
+                    .entries
+                    .iter()
+                    .map(|(key, value)| (key, value)),
+            )
+            .finish()
+    }
+}
+
+/// Hashes a map key consistently with `keys_equal`, so equal keys find the same entry.
+/// Lists and maps can't be keys, since changing them would change their hash.
+fn hash_key(key: &Value, interpreter: &mut Interpreter, at: &Rc<Token>) -> Result<u64> {
+    match key_method(key, "__hash__")? {
+        // An instance with `__hash__` hashes as the value it returns. That value's own
+        // `__hash__` isn't called, so one returning `this` can't recurse forever.
+        Some(method) => {
+            let hash = interpreter.call_value(&method, Vec::new(), at)?;
+            hash_value(&hash, at)
+        }
+        None => hash_value(key, at),
+    }
+}
+
+/// Hashes a value consistently with `==`, or reports it at `at` if it can't be a key.
+fn hash_value(key: &Value, at: &Token) -> Result<u64> {
+    let mut hasher = DefaultHasher::new();
+    std::mem::discriminant(key).hash(&mut hasher);
+    match key {
+        Value::Nil => {}
+        Value::Bool(b) => b.hash(&mut hasher),
+        // `-0.0 == 0.0`, so both hash as zero
+        Value::Number(n) => (n.0 + 0.0).to_bits().hash(&mut hasher),
+        Value::String(s) => s.hash(&mut hasher),
+        Value::NativeFunction(function) => std::ptr::hash(Rc::as_ptr(function), &mut hasher),
+        Value::Function(function) => function.hash(&mut hasher),
+        Value::Class(class) => class.name.hash(&mut hasher),
+        Value::Instance(instance) => instance.hash(&mut hasher),
+        Value::List(_) | Value::Map(_) => {
+            return Err(Error::UnhashableKey {
+                key: key.clone(),
+                line: at.get_line(),
+                column: at.get_column(),
+            })
+        }
+    }
+    Ok(hasher.finish())
+}
+
+/// Compares a key with one in the map, calling `__eq__` with the map's key if the key
+/// being looked up is an instance that defines it, and comparing by identity otherwise.
+fn keys_equal(
+    key: &Value,
+    candidate: &Value,
+    interpreter: &mut Interpreter,
+    at: &Rc<Token>,
+) -> Result<bool> {
+    match key_method(key, "__eq__")? {
+        Some(method) => Ok(interpreter
+            .call_value(&method, vec![candidate.clone()], at)?
+            .is_truthy()),
+        None => Ok(key == candidate),
+    }
+}
+
+/// The method `name` of an instance key, bound to it, if its class defines one.
+fn key_method(key: &Value, name: &str) -> Result<Option<Function>> {
+    let Value::Instance(instance) = key else {
+        return Ok(None);
+    };
+    match instance.class().find_method(name) {
+        Some(method) => Ok(Some(Function::try_from(method)?.bind(instance.clone()))),
+        None => Ok(None),
+    }
+}
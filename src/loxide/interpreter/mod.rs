@@ -1,17 +1,24 @@
-use std::{collections::HashMap, time};
+use std::{
+    collections::HashMap,
+    io::{self, BufRead, Write},
+    rc::Rc,
+    time,
+};
 
-use ordered_float::OrderedFloat;
 use thiserror::Error;
 
+pub use self::value::{Value, TYPE_NAMES};
+
 use self::{
     classes::{Class, Instance},
     environment::Environment,
-    functions::{Callable, Function, NativeFunction},
-    value::Value,
+    functions::{Arity, Callable, Function, FunctionDeclaration},
+    map::Map,
 };
 
 use super::{
-    ast::{Expr, Stmt, Visitor},
+    ast::{Expr, MatchArm, NodeId, Stmt, Visitor},
+    symbol::Symbol,
     token::Token,
     token_type::TokenType,
 };
@@ -19,12 +26,14 @@ use super::{
 mod classes;
 mod environment;
 pub mod functions;
+mod map;
+mod natives;
 mod value;
 
 #[derive(Debug, Error)]
 pub enum Error {
     #[error(
-        "Operator `{operator}` expected one of: [{}], found {} of type {}.",
+        "[line {line}] Operator `{operator}` expected one of: [{}], found {} of type {}.",
         .expected.join(", "),
         .found,
         .found.type_of()
@@ -33,13 +42,34 @@ pub enum Error {
         operator: TokenType,
         expected: Vec<String>,
         found: Value,
+        line: usize,
+        column: usize,
     },
 
-    #[error("Unsupported unary operator `{operator}` on type {}.", .value.type_of())]
-    UnsupportedUnary { operator: TokenType, value: Value },
+    #[error(
+        "[line {line}] Function `{function}` expected one of: [{}], found {} of type {}.",
+        .expected.join(", "),
+        .found,
+        .found.type_of()
+    )]
+    InvalidArgument {
+        function: String,
+        expected: Vec<String>,
+        found: Value,
+        line: usize,
+        column: usize,
+    },
+
+    #[error("[line {line}] Unsupported unary operator `{operator}` on type {}.", .value.type_of())]
+    UnsupportedUnary {
+        operator: TokenType,
+        value: Value,
+        line: usize,
+        column: usize,
+    },
 
     #[error(
-        "Unsupported binary operator `{operator}` on types {} and {}.",
+        "[line {line}] Unsupported binary operator `{operator}` on types {} and {}.",
         .left.type_of(),
         .right.type_of()
     )]
@@ -47,34 +77,190 @@ pub enum Error {
         operator: TokenType,
         left: Value,
         right: Value,
+        line: usize,
+        column: usize,
     },
 
-    #[error("Undefined variable {name}.")]
-    UndefinedVariable { name: String },
+    #[error("[line {line}] Operator `{operator}` expected an integer, found {value}.")]
+    NonIntegerOperand {
+        operator: TokenType,
+        value: Value,
+        line: usize,
+        column: usize,
+    },
+
+    #[error("[line {line}] Shift amount {amount} is out of range 0 to 63.")]
+    InvalidShift {
+        amount: i64,
+        line: usize,
+        column: usize,
+    },
+
+    #[error("[line {line}] Division by zero.")]
+    DivisionByZero { line: usize, column: usize },
+
+    #[error("[line {line}] Undefined variable {name}.")]
+    UndefinedVariable {
+        name: String,
+        line: usize,
+        column: usize,
+    },
+
+    #[error(
+        "[line {line}] Cannot assign to undefined variable {name}; declare it with 'var' first."
+    )]
+    AssignToUndefined {
+        name: String,
+        line: usize,
+        column: usize,
+    },
 
     #[error("Break statement outside of loop.")]
-    Break,
+    Break(Option<Value>),
 
-    #[error("Cannot call non-callable value of type `{}`.", .value.type_of())]
-    NotCallable { value: Value },
+    #[error("Continue statement outside of loop.")]
+    Continue,
+
+    #[error("[line {line}] Cannot call non-callable value of type `{}`.", .value.type_of())]
+    NotCallable {
+        value: Value,
+        line: usize,
+        column: usize,
+    },
 
-    #[error("Expected {expected} arguments but found {found}.")]
-    InvalidArgumentCount { expected: usize, found: usize },
+    #[error("[line {line}] `{name}` expected {expected} arguments but found {found}.")]
+    InvalidArgumentCount {
+        name: String,
+        expected: Arity,
+        found: usize,
+        line: usize,
+        column: usize,
+    },
+
+    /// An error raised inside a function call, with the calls that led to it.
+    #[error("{error}{}", format_stack(.stack))]
+    Traceback {
+        error: Box<Error>,
+        /// The name and call-site line of each active call, outermost first
+        stack: Vec<(String, usize)>,
+    },
+
+    #[error("Stack overflow: calls are nested more than {limit} deep.")]
+    StackOverflow { limit: usize },
 
     #[error(transparent)]
     SystemTimeError(#[from] time::SystemTimeError),
 
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+
     #[error("Return statement outside of function.")]
     Return(Value),
 
-    #[error("Tried to access property `{property}` on non-object `{value}` of type `{}`.", .value.type_of())]
-    PropertyOnNonObject { property: String, value: Value },
+    #[error("[line {line}] Tried to access property `{property}` on non-object `{value}` of type `{}`.", .value.type_of())]
+    PropertyOnNonObject {
+        property: String,
+        value: Value,
+        line: usize,
+        column: usize,
+    },
+
+    #[error("[line {line}] Undefined property `{property}` on object `{value}`.")]
+    UndefinedProperty {
+        property: String,
+        value: Value,
+        line: usize,
+        column: usize,
+    },
+
+    #[error("[line {line}] Can't index into value `{value}` of type `{}`.", .value.type_of())]
+    NotIndexable {
+        value: Value,
+        line: usize,
+        column: usize,
+    },
+
+    #[error("[line {line}] Can't iterate over value `{value}` of type `{}`.", .value.type_of())]
+    NotIterable {
+        value: Value,
+        line: usize,
+        column: usize,
+    },
+
+    #[error("[line {line}] Can't assign to an index of value `{value}` of type `{}`.", .value.type_of())]
+    NotIndexAssignable {
+        value: Value,
+        line: usize,
+        column: usize,
+    },
+
+    #[error("[line {line}] Index must be an integer, found `{index}` of type `{}`.", .index.type_of())]
+    InvalidIndex {
+        index: Value,
+        line: usize,
+        column: usize,
+    },
+
+    #[error("[line {line}] Index {index} out of bounds for length {len}.")]
+    IndexOutOfBounds {
+        index: i64,
+        len: usize,
+        line: usize,
+        column: usize,
+    },
+
+    #[error("[line {line}] Key `{key}` not found in map.")]
+    MissingKey {
+        key: Value,
+        line: usize,
+        column: usize,
+    },
+
+    #[error("[line {line}] Can't use `{key}` of type `{}` as a map key.", .key.type_of())]
+    UnhashableKey {
+        key: Value,
+        line: usize,
+        column: usize,
+    },
+
+    #[error("[line {line}] Invalid range: start {start} is after end {end}.")]
+    InvalidRange {
+        start: usize,
+        end: usize,
+        line: usize,
+        column: usize,
+    },
+
+    #[error("[line {line}] Can't call `{function}` on an empty list.")]
+    EmptyList {
+        function: String,
+        line: usize,
+        column: usize,
+    },
+
+    #[error(
+        "[line {line}] Invalid clamp bounds: lower bound {lo} is greater than upper bound {hi}."
+    )]
+    InvalidClampBounds {
+        lo: f64,
+        hi: f64,
+        line: usize,
+        column: usize,
+    },
 
-    #[error("Undefined property `{property}` on object `{value}`.")]
-    UndefinedProperty { property: String, value: Value },
+    #[error("[line {line}] Assertion failed{}", .message.as_ref().map_or(String::from("."), |m| format!(": {m}")))]
+    AssertionFailed {
+        message: Option<String>,
+        line: usize,
+        column: usize,
+    },
 
-    #[error("Superclass {value} must be a class.")]
-    SuperclassNotAClass { value: Value },
+    #[error("[line {line}] Superclass {value} must be a class.")]
+    SuperclassNotAClass {
+        value: Value,
+        line: usize,
+        column: usize,
+    },
 
     #[error("Failed to convert `{from}` from type `{}` to `{to}`.", .from.type_of())]
     ConversionError { from: Value, to: String },
@@ -82,41 +268,188 @@ pub enum Error {
 
 pub type Result<T, E = Error> = std::result::Result<T, E>;
 
+impl Error {
+    /// The name of the variant, e.g. `UndefinedVariable`.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::InvalidOperand { .. } => "InvalidOperand",
+            Self::InvalidArgument { .. } => "InvalidArgument",
+            Self::UnsupportedUnary { .. } => "UnsupportedUnary",
+            Self::UnsupportedBinary { .. } => "UnsupportedBinary",
+            Self::NonIntegerOperand { .. } => "NonIntegerOperand",
+            Self::InvalidShift { .. } => "InvalidShift",
+            Self::DivisionByZero { .. } => "DivisionByZero",
+            Self::UndefinedVariable { .. } => "UndefinedVariable",
+            Self::AssignToUndefined { .. } => "AssignToUndefined",
+            Self::Break(_) => "Break",
+            Self::Continue => "Continue",
+            Self::NotCallable { .. } => "NotCallable",
+            Self::InvalidArgumentCount { .. } => "InvalidArgumentCount",
+            Self::Traceback { .. } => "Traceback",
+            Self::StackOverflow { .. } => "StackOverflow",
+            Self::SystemTimeError(_) => "SystemTimeError",
+            Self::Io(_) => "Io",
+            Self::Return(_) => "Return",
+            Self::PropertyOnNonObject { .. } => "PropertyOnNonObject",
+            Self::UndefinedProperty { .. } => "UndefinedProperty",
+            Self::NotIndexable { .. } => "NotIndexable",
+            Self::NotIterable { .. } => "NotIterable",
+            Self::NotIndexAssignable { .. } => "NotIndexAssignable",
+            Self::InvalidIndex { .. } => "InvalidIndex",
+            Self::IndexOutOfBounds { .. } => "IndexOutOfBounds",
+            Self::MissingKey { .. } => "MissingKey",
+            Self::UnhashableKey { .. } => "UnhashableKey",
+            Self::InvalidRange { .. } => "InvalidRange",
+            Self::EmptyList { .. } => "EmptyList",
+            Self::InvalidClampBounds { .. } => "InvalidClampBounds",
+            Self::AssertionFailed { .. } => "AssertionFailed",
+            Self::SuperclassNotAClass { .. } => "SuperclassNotAClass",
+            Self::ConversionError { .. } => "ConversionError",
+        }
+    }
+
+    /// The source line the error was raised on, if it was raised at a known token. Errors
+    /// raised inside native functions are reported where the native was called.
+    pub fn line(&self) -> Option<usize> {
+        match self {
+            Self::Traceback { error, .. } => error.line(),
+            Self::InvalidOperand { line, .. }
+            | Self::InvalidArgument { line, .. }
+            | Self::InvalidIndex { line, .. }
+            | Self::IndexOutOfBounds { line, .. }
+            | Self::MissingKey { line, .. }
+            | Self::UnhashableKey { line, .. }
+            | Self::InvalidRange { line, .. }
+            | Self::EmptyList { line, .. }
+            | Self::InvalidClampBounds { line, .. }
+            | Self::AssertionFailed { line, .. }
+            | Self::UnsupportedUnary { line, .. }
+            | Self::UnsupportedBinary { line, .. }
+            | Self::NonIntegerOperand { line, .. }
+            | Self::InvalidShift { line, .. }
+            | Self::DivisionByZero { line, .. }
+            | Self::UndefinedVariable { line, .. }
+            | Self::AssignToUndefined { line, .. }
+            | Self::NotCallable { line, .. }
+            | Self::InvalidArgumentCount { line, .. }
+            | Self::PropertyOnNonObject { line, .. }
+            | Self::UndefinedProperty { line, .. }
+            | Self::NotIndexable { line, .. }
+            | Self::NotIterable { line, .. }
+            | Self::NotIndexAssignable { line, .. }
+            | Self::SuperclassNotAClass { line, .. } => Some(*line),
+            _ => None,
+        }
+    }
+
+    /// The column of the token the error was raised at, alongside `line`.
+    pub fn column(&self) -> Option<usize> {
+        match self {
+            Self::Traceback { error, .. } => error.column(),
+            Self::InvalidOperand { column, .. }
+            | Self::InvalidArgument { column, .. }
+            | Self::InvalidIndex { column, .. }
+            | Self::IndexOutOfBounds { column, .. }
+            | Self::MissingKey { column, .. }
+            | Self::UnhashableKey { column, .. }
+            | Self::InvalidRange { column, .. }
+            | Self::EmptyList { column, .. }
+            | Self::InvalidClampBounds { column, .. }
+            | Self::AssertionFailed { column, .. }
+            | Self::UnsupportedUnary { column, .. }
+            | Self::UnsupportedBinary { column, .. }
+            | Self::NonIntegerOperand { column, .. }
+            | Self::InvalidShift { column, .. }
+            | Self::DivisionByZero { column, .. }
+            | Self::UndefinedVariable { column, .. }
+            | Self::AssignToUndefined { column, .. }
+            | Self::NotCallable { column, .. }
+            | Self::InvalidArgumentCount { column, .. }
+            | Self::PropertyOnNonObject { column, .. }
+            | Self::UndefinedProperty { column, .. }
+            | Self::NotIndexable { column, .. }
+            | Self::NotIterable { column, .. }
+            | Self::NotIndexAssignable { column, .. }
+            | Self::SuperclassNotAClass { column, .. } => Some(*column),
+            _ => None,
+        }
+    }
+}
+
 pub struct Interpreter {
     environment: Environment,
     globals: Environment,
-    locals: HashMap<Expr, usize>,
+    locals: HashMap<NodeId, (usize, usize)>,
+    /// The name and call-site token of each active call, outermost first
+    call_stack: Vec<(String, Rc<Token>)>,
+    /// Where `print` statements write, stdout unless replaced
+    output: Box<dyn Write>,
+    /// Where `read_line` reads from, stdin unless replaced. Stdin isn't held here, since
+    /// buffering it would take input the REPL is waiting for
+    input: Option<Box<dyn BufRead>>,
+    /// The time source for `clock` and timers, the system clock unless replaced
+    clock: Clock,
+    /// How many Lox functions are currently running, and how many may be at once
+    depth: usize,
+    max_depth: usize,
+    /// Where on the native stack the outermost running call started, and how many bytes
+    /// past it nested calls may use
+    stack_base: usize,
+    max_stack: usize,
+}
+
+/// The default limit on nested function calls.
+pub const DEFAULT_MAX_DEPTH: usize = 1000;
+
+/// The default limit on the native stack used by nested function calls. A call takes
+/// several times more stack in debug builds than in release builds, so in debug builds this
+/// is usually reached before `DEFAULT_MAX_DEPTH`. Either way it leaves headroom below the
+/// 8 MiB stack the main thread gets on most platforms.
+pub const DEFAULT_MAX_STACK: usize = 4 * 1024 * 1024;
+
+/// Returns the current time in seconds since the Unix epoch.
+pub type Clock = fn() -> Result<f64>;
+
+impl Default for Interpreter {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Interpreter {
     pub fn new() -> Self {
         let mut globals = Environment::global();
 
-        // Define the clock native function
-        globals.define(
-            "clock".to_string(),
-            Value::NativeFunction(NativeFunction {
-                name: "clock".to_string(),
-                arity: 0,
-                function: |_, _| {
-                    Ok(Value::Number(OrderedFloat(
-                        time::SystemTime::now()
-                            .duration_since(time::UNIX_EPOCH)?
-                            .as_secs_f64(),
-                    )))
-                },
-            }),
-        );
+        // Define the native functions
+        for native in natives::globals() {
+            let name = Symbol::intern(&native.name);
+            globals.define(name, Value::NativeFunction(Rc::new(native)));
+        }
 
         Self {
             environment: globals.clone(),
             globals,
             locals: HashMap::new(),
+            call_stack: Vec::new(),
+            output: Box::new(io::stdout()),
+            input: None,
+            clock: natives::system_clock,
+            depth: 0,
+            max_depth: DEFAULT_MAX_DEPTH,
+            stack_base: 0,
+            max_stack: DEFAULT_MAX_STACK,
         }
     }
 
-    pub fn interpret(&mut self, statements: &[Stmt]) -> Result<()> {
-        statements.iter().try_for_each(|stmt| self.visit_stmt(stmt))
+    /// Runs a program, returning the value of its final statement if that's an expression
+    /// statement, or nil otherwise.
+    pub fn interpret(&mut self, statements: &[Stmt]) -> Result<Value> {
+        statements
+            .iter()
+            .try_fold(Value::Nil, |_, stmt| match stmt {
+                Stmt::Expression(expr) => self.visit_expr(expr),
+                stmt => self.visit_stmt(stmt).map(|_| Value::Nil),
+            })
     }
 
     pub fn execute_block(&mut self, statements: &[Stmt], environment: Environment) -> Result<()> {
@@ -131,22 +464,638 @@ impl Interpreter {
         result // Return result of block
     }
 
-    fn lookup_variable(&self, name: &Token, expr: &Expr) -> Result<Value> {
+    fn lookup_variable(&self, name: &Rc<Token>) -> Result<Value> {
         // Look up the variable in the local or global environment
-        let value = if let Some(distance) = self.locals.get(expr) {
-            self.environment.lookup_at(*distance, name.get_lexeme())
+        let value = if let Some(&(distance, slot)) = self.locals.get(&NodeId::new(name)) {
+            self.environment.lookup_at(distance, slot)
         } else {
-            self.globals.lookup(name.get_lexeme())
+            self.globals.lookup(name.get_symbol())
         };
 
         value.ok_or(Error::UndefinedVariable {
             name: name.get_lexeme(),
+            line: name.get_line(),
+            column: name.get_column(),
         })
     }
 
-    pub fn update_locals(&mut self, locals: HashMap<Expr, usize>) {
+    /// Redirects the output of `print` statements, e.g. into a buffer the host can read.
+    pub fn set_output(&mut self, output: Box<dyn Write>) {
+        self.output = output;
+    }
+
+    /// Replaces stdin as the source of `read_line`, e.g. with input the host has buffered.
+    pub fn set_input(&mut self, input: Box<dyn BufRead>) {
+        self.input = Some(input);
+    }
+
+    pub fn set_clock(&mut self, clock: Clock) {
+        self.clock = clock;
+    }
+
+    /// Limits how deeply function calls can nest before raising `Error::StackOverflow`.
+    pub fn set_max_depth(&mut self, max_depth: usize) {
+        self.max_depth = max_depth;
+    }
+
+    /// Limits how many bytes of native stack nested function calls can use before raising
+    /// `Error::StackOverflow`. Threads with a smaller stack than the main thread, like the
+    /// 2 MiB Rust gives spawned threads by default, need a lower limit.
+    pub fn set_max_stack(&mut self, max_stack: usize) {
+        self.max_stack = max_stack;
+    }
+
+    /// Whether starting another function call would go past either limit on nesting.
+    /// The outermost call records where on the stack it started, to measure from.
+    fn call_limit_reached(&mut self) -> bool {
+        let marker = 0u8;
+        let here = std::ptr::addr_of!(marker) as usize;
+        if self.depth == 0 {
+            self.stack_base = here;
+        }
+        self.depth >= self.max_depth || self.stack_base.abs_diff(here) > self.max_stack
+    }
+
+    pub fn define_global(&mut self, name: &str, value: Value) {
+        self.globals.define(Symbol::intern(name), value);
+    }
+
+    /// Converts an instance whose class defines a `to_string` method by calling it,
+    /// leaving every other value as it is.
+    /// `at` is the token the call is reported at.
+    fn call_to_string(&mut self, value: Value, at: &Rc<Token>) -> Result<Value> {
+        if let Value::Instance(ref instance) = value {
+            if let Some(method) = instance.class().find_method("to_string") {
+                let method = Function::try_from(method)?.bind(instance.clone());
+                return self.call_value(&method, Vec::new(), at);
+            }
+        }
+        Ok(value)
+    }
+
+    /// Calls a value after checking it accepts the number of arguments, with a frame for
+    /// the call on the call stack, so that implicit calls (getters, `to_string`) behave
+    /// like calls written out in the source. `at` is the token the call is reported at.
+    fn call_value(
+        &mut self,
+        callable: &dyn Callable,
+        arguments: Vec<Value>,
+        at: &Rc<Token>,
+    ) -> Result<Value> {
+        if !callable.arity().accepts(arguments.len()) {
+            return Err(Error::InvalidArgumentCount {
+                name: callable.name(),
+                expected: callable.arity(),
+                found: arguments.len(),
+                line: at.get_line(),
+                column: at.get_column(),
+            });
+        }
+
+        self.call_stack.push((callable.name(), at.clone()));
+        let result = callable.call(self, arguments);
+
+        // The innermost call sees the error first, while the whole stack is still active, so
+        // it records the calls that led to the error with the error itself. Errors raised at
+        // the top level have no calls to report.
+        let result = result.map_err(|error| match error {
+            Error::Traceback { .. } | Error::Return(_) | Error::Break(_) | Error::Continue => error,
+            error => Error::Traceback {
+                error: Box::new(error),
+                stack: (self.call_stack.iter())
+                    .map(|(name, token)| (name.clone(), token.get_line()))
+                    .collect(),
+            },
+        });
+        self.call_stack.pop();
+        result
+    }
+
+    /// The token of the innermost active call, for natives that make calls of their own.
+    fn call_site(&self) -> Rc<Token> {
+        let (_, token) = self
+            .call_stack
+            .last()
+            .expect("Natives are only run from a call");
+        token.clone()
+    }
+
+    /// Prepares the operands of `+`, converting an instance added to a string with
+    /// `to_string` so the two can be concatenated.
+    fn concat_operands(
+        &mut self,
+        left: Value,
+        right: Value,
+        operator: &Rc<Token>,
+    ) -> Result<(Value, Value)> {
+        match (left, right) {
+            (left @ Value::String(_), right) => Ok((left, self.call_to_string(right, operator)?)),
+            (left, right @ Value::String(_)) => Ok((self.call_to_string(left, operator)?, right)),
+            operands => Ok(operands),
+        }
+    }
+
+    /// Whether a global name is currently bound to a native function.
+    pub fn is_native(&self, name: &str) -> bool {
+        matches!(
+            self.globals.lookup(Symbol::intern(name)),
+            Some(Value::NativeFunction(_))
+        )
+    }
+
+    pub fn update_locals(&mut self, locals: HashMap<NodeId, (usize, usize)>) {
         self.locals.extend(locals);
     }
+
+    // Each node with more than a few lines of logic is evaluated in its own method, which
+    // keeps the frames of `visit_stmt` and `visit_expr`, and so of every nested call, small.
+
+    fn unary(&mut self, operator: &Rc<Token>, right: &Expr) -> Result<Value> {
+        let right = self.visit_expr(right)?;
+
+        match operator.get_token_type() {
+            TokenType::Minus => match right {
+                Value::Number(n) => Ok(Value::Number(-n)),
+                _ => invalid_operand_error(operator, &["Number"], right),
+            },
+            TokenType::Bang => Ok(Value::Bool(!right.is_truthy())),
+            TokenType::Tilde => Ok(Value::from(!integer_operand(operator, right)? as f64)),
+            op => Err(Error::UnsupportedUnary {
+                operator: op,
+                value: right,
+                line: operator.get_line(),
+                column: operator.get_column(),
+            }),
+        }
+    }
+
+    /// Applies a binary operator to operands that have already been evaluated.
+    fn binary(&mut self, left: Value, operator: &Rc<Token>, right: Value) -> Result<Value> {
+        match operator.get_token_type() {
+            TokenType::Minus => match (left, right) {
+                (Value::Number(l), Value::Number(r)) => Ok(Value::Number(l - r)),
+                (Value::Number(_), right) => invalid_operand_error(operator, &["Number"], right),
+                (left, _) => invalid_operand_error(operator, &["Number"], left),
+            },
+            TokenType::Slash => match (left, right) {
+                (Value::Number(_), Value::Number(r)) if r.0 == 0.0 => Err(Error::DivisionByZero {
+                    line: operator.get_line(),
+                    column: operator.get_column(),
+                }),
+                (Value::Number(l), Value::Number(r)) => Ok(Value::Number(l / r)),
+                (Value::Number(_), right) => invalid_operand_error(operator, &["Number"], right),
+                (left, _) => invalid_operand_error(operator, &["Number"], left),
+            },
+            TokenType::Div => match (left, right) {
+                (Value::Number(_), Value::Number(r)) if r.0 == 0.0 => Err(Error::DivisionByZero {
+                    line: operator.get_line(),
+                    column: operator.get_column(),
+                }),
+                (Value::Number(l), Value::Number(r)) => Ok(Value::from((l / r).floor())),
+                (Value::Number(_), right) => invalid_operand_error(operator, &["Number"], right),
+                (left, _) => invalid_operand_error(operator, &["Number"], left),
+            },
+            TokenType::Star => match (left, right) {
+                (Value::Number(l), Value::Number(r)) => Ok(Value::Number(l * r)),
+                (Value::Number(_), right) => invalid_operand_error(operator, &["Number"], right),
+                (left, _) => invalid_operand_error(operator, &["Number"], left),
+            },
+            TokenType::StarStar => match (left, right) {
+                (Value::Number(l), Value::Number(r)) => Ok(Value::from(l.powf(r.0))),
+                (Value::Number(_), right) => invalid_operand_error(operator, &["Number"], right),
+                (left, _) => invalid_operand_error(operator, &["Number"], left),
+            },
+            TokenType::Plus => match self.concat_operands(left, right, operator)? {
+                (Value::Number(l), Value::Number(r)) => Ok(Value::Number(l + r)),
+                (Value::String(l), Value::String(r)) => Ok(Value::String(format!("{l}{r}"))),
+                (Value::Number(_), right) => invalid_operand_error(operator, &["Number"], right),
+                (Value::String(_), right) => invalid_operand_error(operator, &["String"], right),
+                (left, _) => invalid_operand_error(operator, &["Number", "String"], left),
+            },
+            // Strings compare lexicographically by code point, which is case-sensitive:
+            // every uppercase ASCII letter sorts before every lowercase one. Numbers
+            // compare as IEEE floats, so any comparison involving `NaN` is false.
+            TokenType::Greater => match (left, right) {
+                (Value::Number(l), Value::Number(r)) => Ok(Value::Bool(l.0 > r.0)),
+                (Value::String(l), Value::String(r)) => Ok(Value::Bool(l > r)),
+                (Value::Number(_), right) => invalid_operand_error(operator, &["Number"], right),
+                (Value::String(_), right) => invalid_operand_error(operator, &["String"], right),
+                (left, _) => invalid_operand_error(operator, &["Number", "String"], left),
+            },
+            TokenType::GreaterEqual => match (left, right) {
+                (Value::Number(l), Value::Number(r)) => Ok(Value::Bool(l.0 >= r.0)),
+                (Value::String(l), Value::String(r)) => Ok(Value::Bool(l >= r)),
+                (Value::Number(_), right) => invalid_operand_error(operator, &["Number"], right),
+                (Value::String(_), right) => invalid_operand_error(operator, &["String"], right),
+                (left, _) => invalid_operand_error(operator, &["Number", "String"], left),
+            },
+            TokenType::Less => match (left, right) {
+                (Value::Number(l), Value::Number(r)) => Ok(Value::Bool(l.0 < r.0)),
+                (Value::String(l), Value::String(r)) => Ok(Value::Bool(l < r)),
+                (Value::Number(_), right) => invalid_operand_error(operator, &["Number"], right),
+                (Value::String(_), right) => invalid_operand_error(operator, &["String"], right),
+                (left, _) => invalid_operand_error(operator, &["Number", "String"], left),
+            },
+            TokenType::LessEqual => match (left, right) {
+                (Value::Number(l), Value::Number(r)) => Ok(Value::Bool(l.0 <= r.0)),
+                (Value::String(l), Value::String(r)) => Ok(Value::Bool(l <= r)),
+                (Value::Number(_), right) => invalid_operand_error(operator, &["Number"], right),
+                (Value::String(_), right) => invalid_operand_error(operator, &["String"], right),
+                (left, _) => invalid_operand_error(operator, &["Number", "String"], left),
+            },
+            TokenType::Ampersand => bitwise(operator, left, right, |l, r| Some(l & r)),
+            TokenType::Pipe => bitwise(operator, left, right, |l, r| Some(l | r)),
+            TokenType::Caret => bitwise(operator, left, right, |l, r| Some(l ^ r)),
+            TokenType::LessLess => bitwise(operator, left, right, |l, r| {
+                u32::try_from(r).ok().and_then(|r| l.checked_shl(r))
+            }),
+            // An arithmetic shift, so negative numbers stay negative
+            TokenType::GreaterGreater => bitwise(operator, left, right, |l, r| {
+                u32::try_from(r).ok().and_then(|r| l.checked_shr(r))
+            }),
+            TokenType::BangEqual => Ok(Value::Bool(!left.is_equal(&right, self, operator)?)),
+            TokenType::EqualEqual => Ok(Value::Bool(left.is_equal(&right, self, operator)?)),
+            _ => Err(Error::UnsupportedBinary {
+                operator: operator.get_token_type(),
+                left,
+                right,
+                line: operator.get_line(),
+                column: operator.get_column(),
+            }),
+        }
+    }
+
+    fn assign(&mut self, name: &Rc<Token>, value: &Expr) -> Result<Value> {
+        let value = self.visit_expr(value)?;
+        let result = if let Some(&(distance, slot)) = self.locals.get(&NodeId::new(name)) {
+            self.environment.assign_at(distance, slot, value.clone())
+        } else {
+            self.globals.assign(name.get_symbol(), value.clone())
+        };
+
+        if result {
+            Ok(value)
+        } else {
+            Err(Error::AssignToUndefined {
+                name: name.get_lexeme(),
+                line: name.get_line(),
+                column: name.get_column(),
+            })
+        }
+    }
+
+    fn call(&mut self, callee: &Expr, paren: &Rc<Token>, arguments: &[Expr]) -> Result<Value> {
+        let callee = self.visit_expr(callee)?;
+
+        let callable: &dyn Callable = match &callee {
+            Value::NativeFunction(function) => function.as_ref(),
+            Value::Function(function) => function,
+            Value::Class(class) => class,
+            _ => {
+                return Err(Error::NotCallable {
+                    value: callee.clone(),
+                    line: paren.get_line(),
+                    column: paren.get_column(),
+                })
+            }
+        };
+
+        let arguments = arguments
+            .iter()
+            .map(|argument| self.visit_expr(argument))
+            .collect::<Result<Vec<_>>>()?;
+
+        self.call_value(callable, arguments, paren)
+    }
+
+    fn get_property(&mut self, object: &Expr, name: &Rc<Token>) -> Result<Value> {
+        let object = self.visit_expr(object)?;
+
+        if let Value::Instance(ref instance) = object {
+            match instance.get(name) {
+                // Getters run on access instead of returning the bound method
+                Some(Value::Function(getter)) if getter.declaration.is_getter => {
+                    self.call_value(&getter, Vec::new(), name)
+                }
+                Some(value) => Ok(value),
+                None => Err(Error::UndefinedProperty {
+                    property: name.get_lexeme(),
+                    value: object,
+                    line: name.get_line(),
+                    column: name.get_column(),
+                }),
+            }
+        } else if let Value::Class(ref class) = object {
+            let method = class.find_static_method(&name.get_lexeme());
+            method.ok_or(Error::UndefinedProperty {
+                property: name.get_lexeme(),
+                value: object,
+                line: name.get_line(),
+                column: name.get_column(),
+            })
+        } else {
+            Err(Error::PropertyOnNonObject {
+                property: name.get_lexeme(),
+                value: object,
+                line: name.get_line(),
+                column: name.get_column(),
+            })
+        }
+    }
+
+    fn set_property(&mut self, object: &Expr, name: &Rc<Token>, value: &Expr) -> Result<Value> {
+        let object = self.visit_expr(object)?;
+
+        if let Value::Instance(mut instance) = object {
+            let value = self.visit_expr(value)?;
+            instance.set(name, value.clone());
+            Ok(value)
+        } else {
+            Err(Error::PropertyOnNonObject {
+                property: name.get_lexeme(),
+                value: object,
+                line: name.get_line(),
+                column: name.get_column(),
+            })
+        }
+    }
+
+    fn index(&mut self, object: &Expr, index: &Expr, bracket: &Rc<Token>) -> Result<Value> {
+        let object = self.visit_expr(object)?;
+        let index = self.visit_expr(index)?;
+
+        match object {
+            Value::List(list) => {
+                let list = list.borrow();
+                Ok(list[checked_index(&index, list.len(), bracket)?].clone())
+            }
+            Value::String(s) => {
+                let i = checked_index(&index, s.chars().count(), bracket)?;
+                Ok(Value::String(s.chars().skip(i).take(1).collect()))
+            }
+            Value::Map(map) => match map.get(&index, self, bracket)? {
+                Some(value) => Ok(value),
+                None => Err(Error::MissingKey {
+                    key: index,
+                    line: bracket.get_line(),
+                    column: bracket.get_column(),
+                }),
+            },
+            _ => Err(Error::NotIndexable {
+                value: object,
+                line: bracket.get_line(),
+                column: bracket.get_column(),
+            }),
+        }
+    }
+
+    fn set_index(
+        &mut self,
+        object: &Expr,
+        index: &Expr,
+        bracket: &Rc<Token>,
+        value: &Expr,
+    ) -> Result<Value> {
+        let object = self.visit_expr(object)?;
+        let index = self.visit_expr(index)?;
+
+        match object {
+            Value::List(list) => {
+                let value = self.visit_expr(value)?;
+                let mut list = list.borrow_mut();
+                let i = checked_index(&index, list.len(), bracket)?;
+                list[i] = value.clone();
+                Ok(value)
+            }
+            // Setting a key that isn't in the map adds it
+            Value::Map(map) => {
+                let value = self.visit_expr(value)?;
+                map.insert(index, value.clone(), self, bracket)?;
+                Ok(value)
+            }
+            _ => Err(Error::NotIndexAssignable {
+                value: object,
+                line: bracket.get_line(),
+                column: bracket.get_column(),
+            }),
+        }
+    }
+
+    fn super_method(&mut self, keyword: &Rc<Token>, method: &Rc<Token>) -> Result<Value> {
+        // `super` and `this` are each the only variable in their scope
+        let (distance, _) = *self
+            .locals
+            .get(&NodeId::new(keyword))
+            .expect("Super expression not in scope");
+        let superclass = self
+            .environment
+            .lookup_at(distance, 0)
+            .expect("Superclass not found in environment");
+
+        let object = Instance::try_from(
+            self.environment
+                .lookup_at(distance - 1, 0)
+                .expect("`this` not found in environment"),
+        )?;
+
+        let super_method =
+            Rc::<Class>::try_from(superclass.clone())?.find_method(&method.get_lexeme());
+
+        if let Some(super_method) = super_method {
+            let super_method = Function::try_from(super_method)?.bind(object);
+            if super_method.declaration.is_getter {
+                self.call_value(&super_method, Vec::new(), method)
+            } else {
+                Ok(Value::Function(super_method))
+            }
+        } else {
+            Err(Error::UndefinedProperty {
+                property: method.get_lexeme(),
+                value: superclass,
+                line: method.get_line(),
+                column: method.get_column(),
+            })
+        }
+    }
+
+    fn print(&mut self, keyword: &Rc<Token>, value: &Expr) -> Result<()> {
+        let value = self.visit_expr(value)?;
+        let value = self.call_to_string(value, keyword)?;
+        writeln!(self.output, "{value}")?;
+        Ok(())
+    }
+
+    fn while_loop(
+        &mut self,
+        condition: &Expr,
+        body: &Stmt,
+        increment: &Option<Expr>,
+        fresh_bindings: bool,
+    ) -> Result<()> {
+        while self.visit_expr(condition)?.is_truthy() {
+            match self.visit_stmt(body) {
+                Err(Error::Break(_)) => break,
+                Err(Error::Continue) => {}
+                result => result?,
+            };
+
+            // Closures created in the body keep this iteration's scope, and the
+            // increment moves on in a copy. The enclosing block restores the
+            // environment it started with once the loop ends.
+            if fresh_bindings {
+                self.environment = self.environment.copy_scope();
+            }
+
+            if let Some(increment) = increment {
+                self.visit_expr(increment)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn for_in(&mut self, name: &Rc<Token>, iterable: &Expr, body: &Stmt) -> Result<()> {
+        // Lists are copied up front, so changing the list in the body doesn't
+        // change which elements are visited
+        let elements = match self.visit_expr(iterable)? {
+            Value::List(elements) => elements.borrow().clone(),
+            Value::String(s) => s.chars().map(|c| Value::String(c.to_string())).collect(),
+            // A map is iterated over its keys, in insertion order
+            Value::Map(map) => map.entries().into_iter().map(|(key, _)| key).collect(),
+            value => {
+                return Err(Error::NotIterable {
+                    value,
+                    line: name.get_line(),
+                    column: name.get_column(),
+                })
+            }
+        };
+
+        for element in elements {
+            let mut environment = self.environment.nest();
+            environment.define(name.get_symbol(), element);
+
+            match self.execute_block(std::slice::from_ref(body), environment) {
+                Err(Error::Break(_)) => break,
+                Err(Error::Continue) => {}
+                result => result?,
+            };
+        }
+        Ok(())
+    }
+
+    fn switch(
+        &mut self,
+        keyword: &Rc<Token>,
+        subject: &Expr,
+        cases: &[(Expr, Vec<Stmt>)],
+        default: &Option<Vec<Stmt>>,
+    ) -> Result<()> {
+        let subject = self.visit_expr(subject)?;
+
+        // Run the first matching case, falling back to the default
+        let mut body = default.as_ref();
+        for (value, statements) in cases {
+            let value = self.visit_expr(value)?;
+            if subject.is_equal(&value, self, keyword)? {
+                body = Some(statements);
+                break;
+            }
+        }
+
+        if let Some(statements) = body {
+            self.execute_block(statements, self.environment.nest())?;
+        }
+        Ok(())
+    }
+
+    fn match_type(
+        &mut self,
+        subject: &Expr,
+        arms: &[MatchArm],
+        else_branch: &Option<Box<Stmt>>,
+    ) -> Result<()> {
+        let subject = self.visit_expr(subject)?;
+
+        // Run the first arm for the subject's type, falling back to the else branch
+        let arm = (arms.iter()).find(|arm| arm.type_name.get_lexeme() == subject.type_of());
+        let mut environment = self.environment.nest();
+        let body = match arm {
+            Some(arm) => {
+                if let Some(binding) = &arm.binding {
+                    environment.define(binding.get_symbol(), subject);
+                }
+                &arm.body
+            }
+            None => match else_branch {
+                Some(body) => body,
+                None => return Ok(()),
+            },
+        };
+        self.execute_block(std::slice::from_ref(body), environment)?;
+        Ok(())
+    }
+
+    fn class(
+        &mut self,
+        name: &Rc<Token>,
+        superclass: &Option<Expr>,
+        methods: &[FunctionDeclaration],
+        static_methods: &[FunctionDeclaration],
+    ) -> Result<()> {
+        let superclass = if let Some(superclass) = superclass {
+            let superclass = self.visit_expr(superclass)?;
+            match superclass {
+                Value::Class(class) => Ok(Some(Box::new(Value::Class(class)))),
+                _ => Err(Error::SuperclassNotAClass {
+                    value: superclass,
+                    line: name.get_line(),
+                    column: name.get_column(),
+                }),
+            }
+        } else {
+            Ok(None)
+        }?;
+
+        if let Some(superclass) = superclass.clone() {
+            self.environment = self.environment.nest();
+            self.environment
+                .define(Symbol::intern("super"), *superclass);
+        }
+
+        let mut class_methods = HashMap::new();
+        for method in methods {
+            let function = if method.name.get_lexeme() == "init" {
+                Function::new_init(method.clone(), self.environment.clone())
+            } else {
+                Function::new(method.clone(), self.environment.clone())
+            };
+            class_methods.insert(method.name.get_lexeme(), Value::Function(function));
+        }
+
+        if superclass.is_some() {
+            self.environment = self.environment.enclosing();
+        }
+
+        // Static methods close over the class's own environment, without `super`
+        let class_static_methods = (static_methods.iter())
+            .map(|method| {
+                let function = Function::new(method.clone(), self.environment.clone());
+                (method.name.get_lexeme(), Value::Function(function))
+            })
+            .collect();
+
+        let class = Rc::new(Class {
+            name: name.get_lexeme(),
+            superclass,
+            methods: Rc::new(class_methods),
+            static_methods: class_static_methods,
+        });
+
+        // Defined only once the class is complete, as a local takes the next slot
+        self.environment
+            .define(name.get_symbol(), Value::Class(class));
+        Ok(())
+    }
 }
 
 impl Visitor<Result<Value>, Result<()>> for Interpreter {
@@ -156,14 +1105,19 @@ impl Visitor<Result<Value>, Result<()>> for Interpreter {
                 self.visit_expr(expr)?;
             }
 
-            Stmt::Print(expr) => println!("{}", self.visit_expr(expr)?),
+            Stmt::Print { keyword, value } => self.print(keyword, value)?,
 
             Stmt::Var { name, initializer } => {
                 let value = match initializer {
                     Some(expr) => self.visit_expr(expr)?,
                     None => Value::Nil,
                 };
-                self.environment.define(name.get_lexeme(), value);
+                self.environment.define(name.get_symbol(), value);
+            }
+
+            Stmt::Const { name, initializer } => {
+                let value = self.visit_expr(initializer)?;
+                self.environment.define(name.get_symbol(), value);
             }
 
             Stmt::Block(statements) => self.execute_block(statements, self.environment.nest())?,
@@ -182,21 +1136,46 @@ impl Visitor<Result<Value>, Result<()>> for Interpreter {
                 }
             }
 
-            Stmt::While { condition, body } => {
-                while self.visit_expr(condition)?.is_truthy() {
-                    match self.visit_stmt(body) {
-                        Err(Error::Break) => break,
-                        result => result?,
-                    };
-                }
+            Stmt::While {
+                condition,
+                body,
+                increment,
+                fresh_bindings,
+            } => self.while_loop(condition, body, increment, *fresh_bindings)?,
+
+            Stmt::ForIn {
+                name,
+                iterable,
+                body,
+            } => self.for_in(name, iterable, body)?,
+
+            Stmt::Switch {
+                keyword,
+                subject,
+                cases,
+                default,
+            } => self.switch(keyword, subject, cases, default)?,
+
+            Stmt::Match {
+                subject,
+                arms,
+                else_branch,
+            } => self.match_type(subject, arms, else_branch)?,
+
+            Stmt::Break { value, .. } => {
+                let value = match value {
+                    Some(expr) => Some(self.visit_expr(expr)?),
+                    None => None,
+                };
+                return Err(Error::Break(value));
             }
 
-            Stmt::Break => return Err(Error::Break),
+            Stmt::Continue { .. } => return Err(Error::Continue),
 
             Stmt::Function(declaration) => {
                 let function = Function::new(declaration.clone(), self.environment.clone());
                 self.environment
-                    .define(declaration.name.get_lexeme(), Value::Function(function));
+                    .define(declaration.name.get_symbol(), Value::Function(function));
             }
 
             Stmt::Return { value, .. } => {
@@ -211,47 +1190,10 @@ impl Visitor<Result<Value>, Result<()>> for Interpreter {
                 name,
                 superclass,
                 methods,
-            } => {
-                let superclass = if let Some(superclass) = superclass {
-                    let superclass = self.visit_expr(superclass)?;
-                    match superclass {
-                        Value::Class(class) => Ok(Some(Box::new(Value::Class(class)))),
-                        _ => Err(Error::SuperclassNotAClass { value: superclass }),
-                    }
-                } else {
-                    Ok(None)
-                }?;
-
-                self.environment.define(name.get_lexeme(), Value::Nil);
+                static_methods,
+            } => self.class(name, superclass, methods, static_methods)?,
 
-                if let Some(superclass) = superclass.clone() {
-                    self.environment = self.environment.nest();
-                    self.environment.define("super".to_string(), *superclass);
-                }
-
-                let mut class_methods = HashMap::new();
-                for method in methods {
-                    let function = if method.name.get_lexeme() == "init" {
-                        Function::new_init(method.clone(), self.environment.clone())
-                    } else {
-                        Function::new(method.clone(), self.environment.clone())
-                    };
-                    class_methods.insert(method.name.get_lexeme(), Value::Function(function));
-                }
-
-                let class = Class {
-                    name: name.get_lexeme(),
-                    superclass,
-                    methods: class_methods,
-                };
-
-                if class.superclass.is_some() {
-                    self.environment = self.environment.enclosing();
-                }
-
-                self.environment
-                    .assign(name.get_lexeme(), Value::Class(class));
-            }
+            Stmt::Comment { .. } => {}
         }
 
         Ok(())
@@ -261,24 +1203,36 @@ impl Visitor<Result<Value>, Result<()>> for Interpreter {
         match expr {
             Expr::Literal(literal) => Value::try_from(literal),
 
-            Expr::Grouping(expr) => self.visit_expr(expr),
+            Expr::ListLiteral(elements) => {
+                let elements = elements
+                    .iter()
+                    .map(|element| self.visit_expr(element))
+                    .collect::<Result<Vec<_>>>()?;
+                Ok(Value::list(elements))
+            }
 
-            Expr::Unary { operator, right } => {
-                let right = self.visit_expr(right)?;
+            Expr::MapLiteral { brace, entries } => {
+                let map = Map::new();
+                for (key, value) in entries {
+                    let key = self.visit_expr(key)?;
+                    let value = self.visit_expr(value)?;
+                    map.insert(key, value, self, brace)?;
+                }
+                Ok(Value::Map(map))
+            }
 
-                match operator.get_token_type() {
-                    TokenType::Minus => match right {
-                        Value::Number(n) => Ok(Value::Number(-n)),
-                        _ => invalid_operand_error(operator, &["Number"], right),
-                    },
-                    TokenType::Bang => Ok(Value::Bool(!right.is_truthy())),
-                    op => Err(Error::UnsupportedUnary {
-                        operator: op,
-                        value: right,
-                    }),
+            Expr::Grouping(expr) => self.visit_expr(expr),
+
+            Expr::Comma(exprs) => {
+                let mut value = Value::Nil;
+                for expr in exprs {
+                    value = self.visit_expr(expr)?;
                 }
+                Ok(value)
             }
 
+            Expr::Unary { operator, right } => self.unary(operator, right),
+
             Expr::Binary {
                 left,
                 operator,
@@ -286,99 +1240,12 @@ impl Visitor<Result<Value>, Result<()>> for Interpreter {
             } => {
                 let left = self.visit_expr(left)?;
                 let right = self.visit_expr(right)?;
-
-                match operator.get_token_type() {
-                    TokenType::Minus => match (left, right) {
-                        (Value::Number(l), Value::Number(r)) => Ok(Value::Number(l - r)),
-                        (Value::Number(_), right) => {
-                            invalid_operand_error(operator, &["Number"], right)
-                        }
-                        (left, _) => invalid_operand_error(operator, &["Number"], left),
-                    },
-                    TokenType::Slash => match (left, right) {
-                        (Value::Number(l), Value::Number(r)) => Ok(Value::Number(l / r)),
-                        (Value::Number(_), right) => {
-                            invalid_operand_error(operator, &["Number"], right)
-                        }
-                        (left, _) => invalid_operand_error(operator, &["Number"], left),
-                    },
-                    TokenType::Star => match (left, right) {
-                        (Value::Number(l), Value::Number(r)) => Ok(Value::Number(l * r)),
-                        (Value::Number(_), right) => {
-                            invalid_operand_error(operator, &["Number"], right)
-                        }
-                        (left, _) => invalid_operand_error(operator, &["Number"], left),
-                    },
-                    TokenType::Plus => match (left, right) {
-                        (Value::Number(l), Value::Number(r)) => Ok(Value::Number(l + r)),
-                        (Value::String(l), Value::String(r)) => {
-                            Ok(Value::String(format!("{l}{r}")))
-                        }
-                        (Value::Number(_), right) => {
-                            invalid_operand_error(operator, &["Number"], right)
-                        }
-                        (Value::String(_), right) => {
-                            invalid_operand_error(operator, &["String"], right)
-                        }
-                        (left, _) => invalid_operand_error(operator, &["Number", "String"], left),
-                    },
-                    TokenType::Greater => match (left, right) {
-                        (Value::Number(l), Value::Number(r)) => Ok(Value::Bool(l > r)),
-                        (Value::Number(_), right) => {
-                            invalid_operand_error(operator, &["Number"], right)
-                        }
-                        (left, _) => invalid_operand_error(operator, &["Number"], left),
-                    },
-                    TokenType::GreaterEqual => match (left, right) {
-                        (Value::Number(l), Value::Number(r)) => Ok(Value::Bool(l >= r)),
-                        (Value::Number(_), right) => {
-                            invalid_operand_error(operator, &["Number"], right)
-                        }
-                        (left, _) => invalid_operand_error(operator, &["Number"], left),
-                    },
-                    TokenType::Less => match (left, right) {
-                        (Value::Number(l), Value::Number(r)) => Ok(Value::Bool(l < r)),
-                        (Value::Number(_), right) => {
-                            invalid_operand_error(operator, &["Number"], right)
-                        }
-                        (left, _) => invalid_operand_error(operator, &["Number"], left),
-                    },
-                    TokenType::LessEqual => match (left, right) {
-                        (Value::Number(l), Value::Number(r)) => Ok(Value::Bool(l <= r)),
-                        (Value::Number(_), right) => {
-                            invalid_operand_error(operator, &["Number"], right)
-                        }
-                        (left, _) => invalid_operand_error(operator, &["Number"], left),
-                    },
-                    TokenType::BangEqual => Ok(Value::Bool(left != right)),
-                    TokenType::EqualEqual => Ok(Value::Bool(left == right)),
-                    _ => Err(Error::UnsupportedBinary {
-                        operator: operator.get_token_type(),
-                        left,
-                        right,
-                    }),
-                }
+                self.binary(left, operator, right)
             }
 
-            Expr::Variable(name) | Expr::This(name) => self.lookup_variable(name, expr),
+            Expr::Variable(name) | Expr::This(name) => self.lookup_variable(name),
 
-            Expr::Assign { name, value } => {
-                let value = self.visit_expr(value)?;
-                let result = if let Some(distance) = self.locals.get(expr) {
-                    self.environment
-                        .assign_at(*distance, name.get_lexeme(), value.clone())
-                } else {
-                    self.globals.assign(name.get_lexeme(), value.clone())
-                };
-
-                if result {
-                    Ok(value)
-                } else {
-                    Err(Error::UndefinedVariable {
-                        name: name.get_lexeme(),
-                    })
-                }
-            }
+            Expr::Assign { name, value } => self.assign(name, value),
 
             Expr::Logical {
                 left,
@@ -399,105 +1266,81 @@ impl Visitor<Result<Value>, Result<()>> for Interpreter {
                 self.visit_expr(right)
             }
 
+            Expr::Ternary {
+                condition,
+                then_branch,
+                else_branch,
+            } => {
+                // Only evaluate the branch that is taken
+                if self.visit_expr(condition)?.is_truthy() {
+                    self.visit_expr(then_branch)
+                } else {
+                    self.visit_expr(else_branch)
+                }
+            }
+
             Expr::Call {
                 callee,
-                paren: _,
+                paren,
                 arguments,
-            } => {
-                let callee = self.visit_expr(callee)?;
-
-                let callable: Box<dyn Callable> = match callee {
-                    Value::NativeFunction(function) => Box::new(function),
-                    Value::Function(function) => Box::new(function),
-                    Value::Class(class) => Box::new(class),
-                    _ => return Err(Error::NotCallable { value: callee }),
-                };
-
-                let arguments = arguments
-                    .iter()
-                    .map(|argument| self.visit_expr(argument))
-                    .collect::<Result<Vec<_>>>()?;
+            } => self.call(callee, paren, arguments),
 
-                if arguments.len() != callable.arity() {
-                    return Err(Error::InvalidArgumentCount {
-                        expected: callable.arity(),
-                        found: arguments.len(),
-                    });
+            Expr::Loop(body) => loop {
+                match self.execute_block(body, self.environment.nest()) {
+                    Err(Error::Break(value)) => return Ok(value.unwrap_or(Value::Nil)),
+                    Err(Error::Continue) => {}
+                    result => result?,
                 }
-
-                callable.call(self, arguments)
-            }
+            },
 
             Expr::Lambda(lambda) => Ok(Value::Function(Function::new(
                 lambda.clone(),
                 self.environment.clone(),
             ))),
 
-            Expr::Get { object, name } => {
-                let object = self.visit_expr(object)?;
-
-                if let Value::Instance(ref instance) = object {
-                    instance.get(name).ok_or(Error::UndefinedProperty {
-                        property: name.get_lexeme(),
-                        value: object,
-                    })
-                } else {
-                    Err(Error::PropertyOnNonObject {
-                        property: name.get_lexeme(),
-                        value: object,
-                    })
-                }
-            }
+            Expr::Get { object, name } => self.get_property(object, name),
 
             Expr::Set {
                 object,
                 name,
                 value,
-            } => {
-                let object = self.visit_expr(object)?;
+            } => self.set_property(object, name, value),
 
-                if let Value::Instance(mut instance) = object {
-                    let value = self.visit_expr(value)?;
-                    instance.set(name, value.clone());
-                    Ok(value)
-                } else {
-                    Err(Error::PropertyOnNonObject {
-                        property: name.get_lexeme(),
-                        value: object,
-                    })
-                }
-            }
-
-            Expr::Super { method, .. } => {
-                let distance = self
-                    .locals
-                    .get(expr)
-                    .expect("Super expression not in scope");
-                let superclass = self
-                    .environment
-                    .lookup_at(*distance, "super".to_string())
-                    .expect("Superclass not found in environment");
+            Expr::Index {
+                object,
+                index,
+                bracket,
+            } => self.index(object, index, bracket),
 
-                let object = Instance::try_from(
-                    self.environment
-                        .lookup_at(*distance - 1, "this".to_string())
-                        .expect("`this` not found in environment"),
-                )?;
+            Expr::SetIndex {
+                object,
+                index,
+                bracket,
+                value,
+            } => self.set_index(object, index, bracket, value),
 
-                let super_method =
-                    Class::try_from(superclass.clone())?.find_method(&method.get_lexeme());
+            Expr::Super { keyword, method } => self.super_method(keyword, method),
+        }
+    }
+}
 
-                if let Some(method) = super_method {
-                    Ok(Value::Function(Function::try_from(method)?.bind(object)))
-                } else {
-                    Err(Error::UndefinedProperty {
-                        property: method.get_lexeme(),
-                        value: superclass,
-                    })
-                }
-            }
+/// Lists the calls of a traceback innermost first, collapsing runs of the same call, like
+/// those left by deep recursion.
+pub fn format_stack(stack: &[(String, usize)]) -> String {
+    let mut result = String::new();
+    let mut frames = stack.iter().rev().peekable();
+    while let Some(frame @ (name, line)) = frames.next() {
+        result.push_str(&format!("\n    in {name} (line {line})"));
+
+        let mut repeats = 0;
+        while frames.next_if_eq(&frame).is_some() {
+            repeats += 1;
+        }
+        if repeats > 0 {
+            result.push_str(&format!("\n    ... repeated {repeats} more times"));
         }
     }
+    result
 }
 
 fn invalid_operand_error<V, S: ToString>(
@@ -509,5 +1352,959 @@ fn invalid_operand_error<V, S: ToString>(
         operator: operator.get_token_type(),
         expected: expected.iter().map(ToString::to_string).collect(),
         found,
+        line: operator.get_line(),
+        column: operator.get_column(),
     })
 }
+
+/// Converts an operand of a bitwise operator into an integer.
+fn integer_operand(operator: &Token, value: Value) -> Result<i64> {
+    match value {
+        // Only whole numbers that fit in an `i64` are accepted
+        Value::Number(n) if n.fract() == 0.0 && n.abs() < i64::MAX as f64 => Ok(n.0 as i64),
+        Value::Number(_) => Err(Error::NonIntegerOperand {
+            operator: operator.get_token_type(),
+            value,
+            line: operator.get_line(),
+            column: operator.get_column(),
+        }),
+        _ => invalid_operand_error(operator, &["Number"], value),
+    }
+}
+
+/// Applies a bitwise operator to integer operands. Only shifts can fail, when the shift
+/// amount is negative or too large.
+fn bitwise(
+    operator: &Token,
+    left: Value,
+    right: Value,
+    op: fn(i64, i64) -> Option<i64>,
+) -> Result<Value> {
+    let l = integer_operand(operator, left)?;
+    let r = integer_operand(operator, right)?;
+    match op(l, r) {
+        Some(n) => Ok(Value::from(n as f64)),
+        None => Err(Error::InvalidShift {
+            amount: r,
+            line: operator.get_line(),
+            column: operator.get_column(),
+        }),
+    }
+}
+
+/// Reports an argument of the wrong type, at the token of the call it was passed to.
+fn invalid_argument_error<V, S: ToString>(
+    function: &str,
+    expected: &[S],
+    found: Value,
+    at: &Token,
+) -> Result<V> {
+    Err(Error::InvalidArgument {
+        function: function.to_string(),
+        expected: expected.iter().map(ToString::to_string).collect(),
+        found,
+        line: at.get_line(),
+        column: at.get_column(),
+    })
+}
+
+/// Converts an index value into a position within a sequence of length `len`. Errors are
+/// reported at `at`, the bracket of an index expression or the paren of a native call.
+fn checked_index(index: &Value, len: usize, at: &Token) -> Result<usize> {
+    checked_position(index, len, len.checked_sub(1), at)
+}
+
+/// Like `checked_index`, but also accepts the position just past the end of the sequence.
+fn checked_bound(index: &Value, len: usize, at: &Token) -> Result<usize> {
+    checked_position(index, len, Some(len), at)
+}
+
+fn checked_position(index: &Value, len: usize, max: Option<usize>, at: &Token) -> Result<usize> {
+    match index {
+        Value::Number(n) if n.fract() == 0.0 => {
+            let i = n.0 as i64;
+            if i < 0 || max.is_none_or(|max| i as usize > max) {
+                Err(Error::IndexOutOfBounds {
+                    index: i,
+                    len,
+                    line: at.get_line(),
+                    column: at.get_column(),
+                })
+            } else {
+                Ok(i as usize)
+            }
+        }
+        _ => Err(Error::InvalidIndex {
+            index: index.clone(),
+            line: at.get_line(),
+            column: at.get_column(),
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Error, Value};
+    use crate::loxide::{
+        test_support::{error, output, session},
+        Error as LoxideError,
+    };
+
+    #[test]
+    fn values_and_errors_stay_small() {
+        // Both are moved through every evaluation step, so their size adds up in deep calls
+        assert!(std::mem::size_of::<Value>() <= 32);
+        assert!(std::mem::size_of::<Error>() <= 128);
+    }
+
+    #[test]
+    fn runaway_recursion_stops_before_the_main_thread_stack_runs_out() {
+        // Spawned threads get less stack than the main thread, so make one as big
+        let source = "fn f(n) { { if (true) { var x = [n]; return f(n + 1); } } } f(0);";
+        let error = std::thread::Builder::new()
+            .stack_size(8 * 1024 * 1024)
+            .spawn(move || runtime_error(source).to_string())
+            .unwrap()
+            .join()
+            .unwrap();
+        assert!(error.starts_with("Stack overflow"), "{error}");
+    }
+
+    #[test]
+    fn index_and_native_argument_errors_have_positions() {
+        let source = "var l = [1];\nprint l[5];";
+        assert_eq!(
+            error(source).render(source),
+            "[line 2] Index 5 out of bounds for length 1.\n\
+             2 | print l[5];\n  \
+             |          ^"
+        );
+
+        let positions =
+            ["l[nil];", "len(1);", "pop([]);", "substring(\"ab\", 2, 1);"].map(|statement| {
+                let diagnostics = error(&format!("var l = [1];\n{statement}")).diagnostics();
+                (
+                    diagnostics[0].kind,
+                    diagnostics[0].line,
+                    diagnostics[0].column,
+                )
+            });
+        assert_eq!(
+            positions,
+            [
+                ("InvalidIndex", Some(2), Some(6)),
+                ("InvalidArgument", Some(2), Some(6)),
+                ("EmptyList", Some(2), Some(7)),
+                ("InvalidRange", Some(2), Some(21)),
+            ]
+        );
+    }
+
+    #[test]
+    fn failed_assertions_point_at_the_call() {
+        let source = "var x = 1;\nassert(x > 1, \"x is small\");";
+        assert_eq!(
+            error(source).render(source),
+            "[line 2] Assertion failed: x is small\n\
+             2 | assert(x > 1, \"x is small\");\n  \
+             |                           ^\n    \
+             in assert (line 2)"
+        );
+
+        let source = "class B {\n  init() { assert(false); }\n}\nB();\nprint \"b ok\";";
+        let error = error(source);
+        assert_eq!(error.diagnostics()[0].kind, "AssertionFailed");
+        assert_eq!(
+            error.diagnostics()[0].stack,
+            [(String::from("B"), 4), (String::from("assert"), 2)]
+        );
+    }
+
+    #[test]
+    fn maps_keep_keys_in_insertion_order() {
+        let source = r#"
+            var m = {"zebra": 1, "apple": 2};
+            m["mango"] = 3;
+            m["zebra"] = 4;
+            for (key in m) print key;
+            print m["zebra"];
+            print len(m);
+        "#;
+        assert_eq!(output(source), "zebra\napple\nmango\n4\n3\n");
+
+        assert_eq!(
+            runtime_error("var m = {};\nprint m[\"x\"];").kind(),
+            "MissingKey"
+        );
+        assert_eq!(runtime_error("var m = {[]: 1};").kind(), "UnhashableKey");
+    }
+
+    #[test]
+    fn maps_print_in_insertion_order() {
+        let source = r#"
+            var m = {"b": 1, "a": {2: nil}};
+            m["c"] = [true];
+            m["b"] = 3;
+            print m;
+            print {};
+        "#;
+        assert_eq!(output(source), "{b: 3, a: {2: nil}, c: [true]}\n{}\n");
+    }
+
+    #[test]
+    fn keys_values_and_contains_query_maps() {
+        let source = r#"
+            var m = {"zebra": 1, "apple": 2};
+            m["mango"] = 3;
+            print keys(m);
+            print values(m);
+            print contains(m, "apple");
+            print contains(m, "pear");
+        "#;
+        assert_eq!(
+            output(source),
+            "[zebra, apple, mango]\n[1, 2, 3]\ntrue\nfalse\n"
+        );
+
+        let kinds =
+            ["keys([]);", "contains({}, []);"].map(|source| error(source).diagnostics()[0].kind);
+        assert_eq!(kinds, ["InvalidArgument", "UnhashableKey"]);
+    }
+
+    #[test]
+    fn instances_with_hash_and_eq_are_keyed_by_value() {
+        let source = r#"
+            class Point {
+                init(x, y) { this.x = x; this.y = y; }
+                __hash__() { return this.x * 31 + this.y; }
+                __eq__(other) { return this.x == other.x and this.y == other.y; }
+            }
+            class Tag {}
+
+            var m = {Point(1, 2): "first"};
+            m[Point(1, 2)] = "second";
+            m[Point(2, 1)] = "third";
+            print len(m);
+            print m[Point(1, 2)];
+            print contains(m, Point(2, 1));
+
+            // Without the methods, instances are keyed by identity
+            var tag = Tag();
+            m[tag] = 1;
+            print contains(m, tag);
+            print contains(m, Tag());
+        "#;
+        assert_eq!(output(source), "2\nsecond\ntrue\ntrue\nfalse\n");
+    }
+
+    #[test]
+    fn match_arms_test_the_type_names_type_gives() {
+        let source = "class A {}
+            fn f() {}
+            for (value in [nil, 1, true, \"s\", [], {}, f, clock, A, A()]) {
+                match value {
+                    Nil => print type(value);
+                    Number => print type(value);
+                    Bool => print type(value);
+                    String => print type(value);
+                    List => print type(value);
+                    Map => print type(value);
+                    Function => print type(value);
+                    Class => print type(value);
+                    Instance => print type(value);
+                }
+            }";
+        assert_eq!(
+            output(source),
+            "Nil\nNumber\nBool\nString\nList\nMap\nFunction\nFunction\nClass\nInstance\n"
+        );
+    }
+
+    fn runtime_error(source: &str) -> Error {
+        match error(source) {
+            LoxideError::Runtime(error) => error,
+            error => panic!("Expected a runtime error, but got: {error}"),
+        }
+    }
+
+    #[test]
+    fn functions_nested_in_methods_capture_this() {
+        let source = "
+            class Counter {
+                init() { this.count = 0; }
+                incrementer() {
+                    return fn () {
+                        this.count = this.count + 1;
+                        return this.count;
+                    };
+                }
+                reader() {
+                    fn outer() {
+                        fn inner() { return this.count; }
+                        return inner;
+                    }
+                    return outer();
+                }
+            }
+            var c = Counter();
+            var increment = c.incrementer();
+            increment();
+            increment();
+            print c.count;
+            print c.reader()();
+
+            var other = Counter();
+            other.count = 10;
+            print other.incrementer()();
+            print c.count;
+        ";
+        assert_eq!(output(source), "2\n2\n11\n2\n");
+    }
+
+    #[test]
+    fn signed_zeros_are_equal_and_neither_is_less() {
+        let source = "
+            print 0 == -0;
+            print -0 < 0 or 0 < -0;
+            print -0 <= 0 and 0 <= -0;
+            print [-0] == [0];
+            print {0: true}[-0];
+        ";
+        assert_eq!(output(source), "true\nfalse\ntrue\ntrue\ntrue\n");
+    }
+
+    #[test]
+    fn to_string_runs_in_its_own_scope() {
+        let source = r#"
+            class A { to_string() { var q = "hi"; return q; } }
+            print A();
+        "#;
+        assert_eq!(output(source), "hi\n");
+    }
+
+    #[test]
+    fn to_string_with_parameters_is_an_argument_count_error() {
+        let source = r#"
+            class A { to_string(a) { var q = "hi"; return q; } }
+            print A();
+        "#;
+        assert!(matches!(
+            runtime_error(source),
+            Error::InvalidArgumentCount { ref name, found: 0, line: 3, .. } if name == "to_string"
+        ));
+    }
+
+    #[test]
+    fn equals_with_the_wrong_arity_is_an_argument_count_error() {
+        let source = "
+            class P { init(x) {} equals(o, extra) { var z = 5; return z == 5; } }
+            print P(1) == P(1);
+        ";
+        assert!(matches!(
+            runtime_error(source),
+            Error::InvalidArgumentCount { ref name, found: 1, line: 3, .. } if name == "equals"
+        ));
+    }
+
+    #[test]
+    fn equals_runs_in_its_own_scope() {
+        let source = "
+            class P {
+                init(x) { this.x = x; }
+                equals(o) { var z = o.x; return this.x == z; }
+            }
+            print P(1) == P(1);
+            switch (P(2)) { case P(2): print \"matched\"; }
+        ";
+        assert_eq!(output(source), "true\nmatched\n");
+    }
+
+    #[test]
+    fn lists_compare_their_elements_with_equals() {
+        let source = "
+            class P {
+                init(x) { this.x = x; }
+                equals(o) { return this.x == o.x; }
+            }
+            print [P(1)] == [P(1)];
+            print [[P(1)], 2] == [[P(1)], 2];
+            print [P(1)] == [P(2)];
+            print [P(1)] != [P(1), P(1)];
+        ";
+        assert_eq!(output(source), "true\ntrue\nfalse\ntrue\n");
+    }
+
+    #[test]
+    fn errors_inside_implicit_calls_have_a_frame() {
+        let source = "
+            class A {
+                to_string() { return nil + 1; }
+            }
+            print A();
+        ";
+        let Error::Traceback { stack, .. } = runtime_error(source) else {
+            panic!("Expected a traceback");
+        };
+        assert_eq!(stack, [(String::from("to_string"), 5)]);
+
+        let Error::Traceback { stack, .. } =
+            runtime_error("class A { size { return nil + 1; } }\nA().size;")
+        else {
+            panic!("Expected a traceback");
+        };
+        assert_eq!(stack, [(String::from("size"), 2)]);
+    }
+
+    #[test]
+    fn chained_assignments_assign_right_to_left_and_give_the_value() {
+        let source = "
+            var a; var b; var c;
+            print a = b = c = 3;
+            print a + b + c;
+
+            var targets = 0;
+            class Point {}
+            var point = Point();
+            fn target() { targets = targets + 1; return point; }
+            var list = [0, 0];
+            fn index() { targets = targets + 1; return 1; }
+            print target().x = list[index()] = 5;
+            print targets;
+            print point.x;
+            print list;
+        ";
+        assert_eq!(output(source), "3\n9\n5\n2\n5\n[0, 5]\n");
+    }
+
+    #[test]
+    fn loops_evaluate_to_the_value_they_break_with() {
+        let source = "
+            var i = 0;
+            var found = loop {
+                i = i + 1;
+                if (i < 3) continue;
+                while (true) break;
+                break i;
+            };
+            print found;
+            print loop { break; };
+        ";
+        assert_eq!(output(source), "3\nnil\n");
+    }
+
+    #[test]
+    fn static_methods_are_called_on_classes_and_inherited() {
+        let source = "
+            class Math {
+                class square(n) { return n * n; }
+                square(n) { return \"instance\"; }
+            }
+            class Sub < Math {}
+            print Math.square(3);
+            print Math().square(3);
+            print Sub.square(4);
+        ";
+        assert_eq!(output(source), "9\ninstance\n16\n");
+        assert!(matches!(
+            runtime_error("class A { class f() {} } A().f();"),
+            Error::UndefinedProperty { .. }
+        ));
+    }
+
+    #[test]
+    fn dividing_by_zero_is_an_error() {
+        assert_eq!(output("print 0 / 5; print 7 / 2;"), "0\n3.5\n");
+        assert!(matches!(
+            runtime_error("var x = 1;\nprint x / 0;"),
+            Error::DivisionByZero { line: 2, column: 9 }
+        ));
+        assert!(matches!(
+            runtime_error("print 1 / -0;"),
+            Error::DivisionByZero { .. }
+        ));
+    }
+
+    #[test]
+    fn strings_compare_by_code_point() {
+        let source = r#"
+            print "apple" < "banana";
+            print "b" <= "b";
+            print "abc" >= "ab";
+            print "" < "a";
+            print "Banana" < "apple";
+            print "B" > "a";
+        "#;
+        assert_eq!(output(source), "true\ntrue\ntrue\ntrue\ntrue\nfalse\n");
+
+        let expected = |source| match runtime_error(source) {
+            Error::InvalidOperand { expected, .. } => expected,
+            error => panic!("Expected an invalid operand, but got: {error}"),
+        };
+        assert_eq!(expected("print nil < 1;"), ["Number", "String"]);
+        assert_eq!(expected("print \"a\" < 1;"), ["String"]);
+        assert_eq!(expected("print 1 < \"a\";"), ["Number"]);
+    }
+
+    #[test]
+    fn exponents_are_right_associative_and_bind_looser_than_negation() {
+        let source = "
+            print 2 ** 3 ** 2;
+            print (2 ** 3) ** 2;
+            print -2 ** 2;
+            print 2 ** -1;
+            print 2 * 3 ** 2;
+        ";
+        assert_eq!(output(source), "512\n64\n4\n0.5\n18\n");
+    }
+
+    #[test]
+    fn nan_is_unequal_and_unordered_everywhere() {
+        let source = "
+            var nan = sqrt(-1);
+            print nan == nan;
+            print nan != nan;
+            print nan < 1 or nan >= 1;
+            print [nan] == [nan];
+            var map = {};
+            map[nan] = 1;
+            map[nan] = 2;
+            print len(map);
+            print contains(map, nan);
+        ";
+        assert_eq!(output(source), "false\ntrue\nfalse\nfalse\n2\nfalse\n");
+    }
+
+    #[test]
+    fn closures_capture_a_fresh_loop_variable_each_iteration() {
+        let source = "
+            var counters = [];
+            for (var i = 0; i < 2; i = i + 1) push(counters, fn () { i = i + 10; return i; });
+            print counters[0]();
+            print counters[0]();
+            print counters[1]();
+
+            var elements = [];
+            for (x in [1, 2]) push(elements, fn () { return x; });
+            print elements[0]();
+            print elements[1]();
+
+            var iterations = 0;
+            for (var j = 0; j < 10; j = j + 1) { j = j + 1; iterations = iterations + 1; }
+            print iterations;
+        ";
+        assert_eq!(output(source), "10\n20\n11\n1\n2\n5\n");
+    }
+
+    #[test]
+    fn functions_classes_and_instances_are_keyed_by_identity() {
+        let source = "
+            fn f() {}
+            fn g() {}
+            class A {}
+            var a = A();
+            var map = {f: 1, g: 2, A: 3, a: 4, clock: 5};
+            print map[f] + map[g] + map[A] + map[a] + map[clock];
+            print f == f and f != g and clock == clock;
+            print contains(map, A());
+            {
+                class A {}
+                print contains(map, A);
+            }
+            print len(map);
+        ";
+        assert_eq!(output(source), "15\ntrue\nfalse\nfalse\n5\n");
+    }
+
+    #[test]
+    fn discarded_errors_leave_no_frames_behind() {
+        // A native that calls a function and ignores whether it failed
+        let (mut loxide, _) = session();
+        loxide.define_native("ignore", 1, |interpreter, arguments| {
+            let Value::Function(function) = &arguments[0] else {
+                panic!("Expected a function");
+            };
+            let at = interpreter.call_site();
+            assert!(interpreter.call_value(function, Vec::new(), &at).is_err());
+            Ok(Value::Nil)
+        });
+
+        let error = loxide
+            .eval("fn f() { return nil + 1; }\nignore(f);\nprint nil + 1;")
+            .unwrap_err();
+        assert!(matches!(
+            error,
+            LoxideError::Runtime(Error::InvalidOperand { line: 3, .. })
+        ));
+    }
+
+    #[test]
+    fn runaway_recursion_through_initializers_stops() {
+        let (mut loxide, printed) = session();
+        loxide.set_max_depth(50);
+        let error = loxide
+            .eval("class A { init() { A(); } }\nA();\nprint \"survived\";")
+            .unwrap_err();
+        assert_eq!(error.diagnostics()[0].kind, "StackOverflow");
+        assert_eq!(printed.text(), "");
+        assert_eq!(
+            output("class B { init() { return; } } print B();"),
+            "<instance of B>\n"
+        );
+    }
+
+    #[test]
+    fn getters_run_on_access_alongside_methods() {
+        let source = r#"
+            class Shape {
+                init(w, h) { this.w = w; this.h = h; }
+                area { return this.w * this.h; }
+                describe() { return "area " + to_string(this.area); }
+            }
+            class Square < Shape {
+                init(side) { super.init(side, side); }
+                area { return super.area + 1; }
+                double() { return this.area * 2; }
+            }
+            var square = Square(3);
+            print square.area;
+            print square.describe();
+            var double = square.double;
+            print double();
+            print Shape(2, 5).area;
+        "#;
+        assert_eq!(output(source), "10\narea 10\n20\n10\n");
+        assert!(matches!(
+            runtime_error("class A { size { return 1; } }\nA().size();"),
+            Error::NotCallable { .. }
+        ));
+    }
+
+    #[test]
+    fn loop_conditions_are_reevaluated_when_they_only_look_invariant() {
+        // Nothing in these loops assigns the condition's variables directly, but calls,
+        // closures, getters and `to_string` all change what the condition evaluates to
+        let source = r#"
+            var limit = 5;
+            fn shrink() { limit = limit - 1; }
+            var i = 0;
+            while (i < limit * 1) { shrink(); i = i + 1; }
+            print i;
+
+            var n = 6;
+            var halve = fn () { n = n - 2; };
+            var j = 0;
+            for (; j < n + 0; j = j + 1) halve();
+            print j;
+
+            class Counter {
+                init() { this.count = 0; }
+                next { this.count = this.count + 1; return this.count; }
+            }
+            var counter = Counter();
+            var k = 0;
+            while (counter.next < 4) k = k + 1;
+            print k;
+
+            class Loud {
+                init() { this.calls = 0; }
+                to_string() { this.calls = this.calls + 1; return "x"; }
+            }
+            var loud = Loud();
+            var m = 0;
+            while (len("" + loud) + loud.calls < 5) m = m + 1;
+            print m;
+        "#;
+        assert_eq!(output(source), "3\n2\n3\n3\n");
+    }
+
+    #[test]
+    fn closures_keep_their_whole_enclosing_scope_alive() {
+        let (mut loxide, _) = session();
+        let source = "var big = [1, 2, 3];
+            fn make() { var local = big; return fn () { return 1; }; }
+            var f = make();";
+        // The resolver knows neither function captures anything...
+        let captures = loxide.captured_variables(source).unwrap();
+        assert!(captures
+            .iter()
+            .all(|function| function.variables.is_empty()));
+
+        // ...but the closure shares `make`'s scope, so `local` lives as long as `f` does
+        loxide.eval(source).unwrap();
+        let Value::List(big) = loxide.eval("big;").unwrap() else {
+            panic!("Expected a list");
+        };
+        assert_eq!(std::rc::Rc::strong_count(&big), 3);
+        loxide.eval("f = nil;").unwrap();
+        assert_eq!(std::rc::Rc::strong_count(&big), 2);
+    }
+
+    #[test]
+    fn closures_see_their_own_binding_and_later_assignments() {
+        // A local function is bound only after its closure is made, and assignments must reach
+        // every closure, so closures can't copy the captured values when they're created
+        let source = "
+            fn outer() {
+                fn countdown(n) { if (n > 0) { print n; countdown(n - 1); } }
+                countdown(2);
+
+                var count = 0;
+                var increment = fn () { count = count + 1; };
+                var read = fn () { return count; };
+                increment();
+                increment();
+                print read();
+                count = count + 1;
+                print read();
+            }
+            outer();
+        ";
+        assert_eq!(output(source), "2\n1\n2\n3\n");
+    }
+
+    #[test]
+    fn bitwise_operators_work_on_integers_and_bind_between_equality_and_comparison() {
+        let source = "
+            print 12 & 10;
+            print 12 | 3;
+            print 12 ^ 10;
+            print ~5;
+            print 1 << 4;
+            print -16 >> 2;
+            print 1 | 2 == 3;
+        ";
+        assert_eq!(output(source), "8\n15\n6\n-6\n16\n-4\ntrue\n");
+
+        assert!(matches!(
+            runtime_error("print 1.5 & 1;"),
+            Error::NonIntegerOperand {
+                line: 1,
+                column: 11,
+                ..
+            }
+        ));
+        assert!(matches!(
+            runtime_error("print 1 << 64;"),
+            Error::InvalidShift { amount: 64, .. }
+        ));
+        assert!(matches!(
+            runtime_error("print 1 >> -1;"),
+            Error::InvalidShift { amount: -1, .. }
+        ));
+        assert!(matches!(
+            runtime_error("print ~nil;"),
+            Error::InvalidOperand { .. }
+        ));
+        // `<` binds tighter, so `&` gets a bool
+        assert!(matches!(
+            runtime_error("print 2 & 3 < 4;"),
+            Error::InvalidOperand { .. }
+        ));
+    }
+
+    #[test]
+    fn conditional_expressions_evaluate_only_the_chosen_branch() {
+        let source = r#"
+            fn loud(x) { print "ran " + x; return x; }
+            print true ? loud("a") : loud("b");
+            print false ? 1 : false ? 2 : 3;
+            var x = nil ? "yes" : "no";
+            print x;
+            print 1 < 2 ? "lt" : "ge";
+        "#;
+        assert_eq!(output(source), "ran a\na\n3\nno\nlt\n");
+
+        let message = |source| error(source).diagnostics()[0].message.clone();
+        assert_eq!(
+            message("print true ? 1;"),
+            "[line 1] Expect ':' after then branch of conditional expression, found ';'."
+        );
+        assert_eq!(
+            message("print true ? 1 : ;"),
+            "[line 1] Expect expression, found ';'."
+        );
+    }
+
+    #[test]
+    fn switch_runs_only_the_first_matching_case() {
+        let source = r#"
+            var calls = 0;
+            fn subject() { calls = calls + 1; return 2; }
+            fn label(n) { print "checked " + to_string(n); return n; }
+            switch (subject()) {
+                case label(1): print "one";
+                case label(2): print "two";
+                case label(3): print "three";
+                default: print "other";
+            }
+            print calls;
+            switch ("x") { case "y": print "y"; default: print "default"; }
+            switch ("x") { case "y": print "y"; }
+        "#;
+        assert_eq!(output(source), "checked 1\nchecked 2\ntwo\n1\ndefault\n");
+
+        assert!(matches!(
+            runtime_error("switch (1) { case missing: print 1; }"),
+            Error::UndefinedVariable { .. }
+        ));
+        let source = "switch (1) { default: print 1; default: print 2; }";
+        assert_eq!(
+            error(source).diagnostics()[0].message,
+            "[line 1] Switch statement can only have one 'default' case."
+        );
+    }
+
+    #[test]
+    fn continue_skips_to_the_next_iteration_of_the_innermost_loop() {
+        // In a `for`, the increment still runs, or the first loop would never end
+        let source = "
+            for (var i = 0; i < 5; i = i + 1) { if (i == 1 or i == 3) continue; print i; }
+            var j = 0;
+            while (j < 4) { j = j + 1; if (j == 2) continue; print j * 10; }
+            for (var a = 0; a < 2; a = a + 1)
+                for (var b = 0; b < 3; b = b + 1) { if (b == 1) continue; print a * 10 + b; }
+            for (x in [1, 2, 3]) { if (x == 2) continue; print x * 100; }
+        ";
+        assert_eq!(
+            output(source),
+            "0\n2\n4\n10\n30\n40\n0\n2\n10\n12\n100\n300\n"
+        );
+
+        // A function body isn't inside the loop it's called from
+        let source = "fn f() { continue; } while (true) f();";
+        assert_eq!(
+            error(source).diagnostics()[0].message,
+            "[line 1] Can't use `continue` outside of a loop."
+        );
+    }
+
+    #[test]
+    fn comma_expressions_evaluate_left_to_right_and_give_the_last_value() {
+        // Commas in argument lists and list literals still separate elements
+        let source = "
+            var log = [];
+            var a = (push(log, 1), push(log, 2), 3);
+            print a;
+            print log;
+            fn f(x, y) { return x + y; }
+            print f(1, 2);
+            print [(1, 2), 3];
+        ";
+        assert_eq!(output(source), "3\n[1, 2]\n3\n[2, 3]\n");
+
+        assert_eq!(
+            error("var a = (1, );").diagnostics()[0].message,
+            "[line 1] Expect expression, found ')'."
+        );
+        // Every operand is evaluated, not just the last
+        assert!(matches!(
+            runtime_error("print (missing, 1);"),
+            Error::UndefinedVariable { .. }
+        ));
+    }
+
+    #[test]
+    fn div_floors_the_quotient_at_the_precedence_of_division() {
+        let source = "
+            print 7 div 2;
+            print -7 div 2;
+            print 7 div -2;
+            print 7.5 div 2;
+            print 1 + 7 div 2 * 2;
+        ";
+        assert_eq!(output(source), "3\n-4\n-4\n3\n7\n");
+
+        assert!(matches!(
+            runtime_error("print 1 div 0;"),
+            Error::DivisionByZero { line: 1, column: 9 }
+        ));
+        assert!(matches!(
+            runtime_error("print \"a\" div 2;"),
+            Error::InvalidOperand { .. }
+        ));
+    }
+
+    #[test]
+    fn assigning_an_undeclared_variable_is_its_own_error() {
+        assert_eq!(
+            output("var w; w = 3; print w; fn f() { w = 4; } f(); print w;"),
+            "3\n4\n"
+        );
+
+        let assignment = runtime_error("x = 1;");
+        assert!(matches!(
+            &assignment,
+            Error::AssignToUndefined { name, line: 1, column: 1 } if name == "x"
+        ));
+        assert_eq!(
+            assignment.to_string(),
+            "[line 1] Cannot assign to undefined variable x; declare it with 'var' first."
+        );
+        assert_eq!(
+            error("fn f() { y = 2; } f();").diagnostics()[0].kind,
+            "AssignToUndefined"
+        );
+        // Reading one is still the read error
+        assert!(matches!(
+            runtime_error("print z;"),
+            Error::UndefinedVariable { .. }
+        ));
+    }
+
+    #[test]
+    fn keywords_can_name_properties_after_a_dot() {
+        let source = r#"
+            class Node {}
+            var node = Node();
+            node.class = "leaf";
+            node.if = 1;
+            node.fn = fn () { return "called"; };
+            print node.class;
+            print node.if;
+            print node.fn();
+            print has_field(node, "class");
+        "#;
+        assert_eq!(output(source), "leaf\n1\ncalled\ntrue\n");
+
+        // Anywhere else, keywords are still not names
+        let message = |source| error(source).diagnostics()[0].message.clone();
+        assert_eq!(
+            message("var class = 1;"),
+            "[line 1] Expect variable name, found 'class'."
+        );
+        assert_eq!(
+            message("var n = 1; print n.1;"),
+            "[line 1] Expect property name after '.', found '1'."
+        );
+    }
+
+    #[test]
+    fn values_convert_to_and_from_rust_types() {
+        let (mut loxide, _) = session();
+        loxide.set_global("count", Value::from(2.5));
+        loxide.set_global("name", Value::from("lox"));
+        loxide.set_global("owned", Value::from(String::from("ide")));
+        loxide.set_global("flag", Value::from(true));
+        loxide.set_global(
+            "items",
+            Value::from(vec![Value::from(1.0), Value::from(2.0)]),
+        );
+
+        let number: f64 = loxide.eval("count * 2;").unwrap().try_into().unwrap();
+        assert_eq!(number, 5.0);
+        let string: String = loxide.eval("name + owned;").unwrap().try_into().unwrap();
+        assert_eq!(string, "loxide");
+        let flag: bool = loxide.eval("!flag;").unwrap().try_into().unwrap();
+        assert!(!flag);
+        assert_eq!(loxide.eval("len(items);").unwrap(), Value::from(2.0));
+
+        let error = f64::try_from(Value::from("1")).unwrap_err();
+        assert!(matches!(&error, Error::ConversionError { to, .. } if to == "Number"));
+        assert_eq!(
+            error.to_string(),
+            "Failed to convert `1` from type `String` to `Number`."
+        );
+        assert!(String::try_from(Value::Nil).is_err());
+        assert!(bool::try_from(Value::from(0.0)).is_err());
+    }
+}
@@ -0,0 +1,1052 @@
+use std::{
+    io::{self, BufRead, Write},
+    time,
+};
+
+use ordered_float::OrderedFloat;
+
+use crate::loxide::token::Token;
+
+use super::{
+    checked_bound,
+    functions::{Arity, Callable, NativeFunction},
+    invalid_argument_error,
+    map::Map,
+    value::Value,
+    Error, Interpreter, Result,
+};
+
+/// Native functions defined in the global environment of every interpreter.
+pub fn globals() -> Vec<NativeFunction> {
+    vec![
+        NativeFunction::new("clock", 0, clock),
+        NativeFunction::new("timerStart", 0, timer_start),
+        NativeFunction::new("elapsed", 1, elapsed),
+        NativeFunction::new("read_line", 0, read_line),
+        NativeFunction::new("write", 1, write),
+        NativeFunction::new("describe", 1, describe),
+        NativeFunction::new("globals", 0, global_names),
+        NativeFunction::new("getClass", 1, get_class),
+        NativeFunction::new("has_field", 2, has_field),
+        NativeFunction::new("delete_field", 2, delete_field),
+        NativeFunction::new("reverse", 1, reverse),
+        NativeFunction::new("rotate", 2, rotate),
+        NativeFunction::new("len", 1, len),
+        NativeFunction::new("zip", 2, zip),
+        NativeFunction::new("enumerate", 1, enumerate),
+        NativeFunction::new("push", 2, push),
+        NativeFunction::new("pop", 1, pop),
+        NativeFunction::new("insert", 3, insert),
+        NativeFunction::new("keys", 1, keys),
+        NativeFunction::new("values", 1, values),
+        NativeFunction::new("contains", 2, contains),
+        NativeFunction::new("sqrt", 1, sqrt),
+        NativeFunction::new("floor", 1, floor),
+        NativeFunction::new("ceil", 1, ceil),
+        NativeFunction::new("abs", 1, abs),
+        NativeFunction::new("pow", 2, pow),
+        NativeFunction::new("round", Arity::Range(1, 2), round),
+        NativeFunction::new("isSafeInteger", 1, is_safe_integer),
+        NativeFunction::new("min", Arity::AtLeast(1), min),
+        NativeFunction::new("max", Arity::AtLeast(1), max),
+        NativeFunction::new("clamp", 3, clamp),
+        NativeFunction::new("to_string", 1, to_string),
+        NativeFunction::new("to_number", 1, to_number),
+        NativeFunction::new("type", 1, type_of),
+        NativeFunction::new("substring", 3, substring),
+        NativeFunction::new("index_of", 2, index_of),
+        NativeFunction::new("to_upper", 1, to_upper),
+        NativeFunction::new("to_lower", 1, to_lower),
+        NativeFunction::new("split", 2, split),
+        NativeFunction::new("join", 2, join),
+        NativeFunction::new("words", 1, words),
+        NativeFunction::new("lines", 1, lines),
+        NativeFunction::new("formatNumber", 4, format_number),
+        NativeFunction::new("assert", Arity::Range(1, 2), assert),
+    ]
+}
+
+/// Extracts the number from a native function argument.
+fn number(function: &str, value: &Value, at: &Token) -> Result<f64> {
+    match value {
+        Value::Number(n) => Ok(n.0),
+        value => invalid_argument_error(function, &["Number"], value.clone(), at),
+    }
+}
+
+/// Extracts a whole number from a native function argument.
+fn integer(function: &str, value: &Value, at: &Token) -> Result<i64> {
+    match value {
+        Value::Number(n) if n.fract() == 0.0 => Ok(n.0 as i64),
+        value => invalid_argument_error(function, &["Integer"], value.clone(), at),
+    }
+}
+
+/// Extracts the map from a native function argument.
+fn map(function: &str, value: &Value, at: &Token) -> Result<Map> {
+    match value {
+        Value::Map(map) => Ok(map.clone()),
+        value => invalid_argument_error(function, &["Map"], value.clone(), at),
+    }
+}
+
+/// Extracts the string from a native function argument.
+fn string<'a>(function: &str, value: &'a Value, at: &Token) -> Result<&'a str> {
+    match value {
+        Value::String(s) => Ok(s),
+        value => invalid_argument_error(function, &["String"], value.clone(), at),
+    }
+}
+
+fn clock(interpreter: &mut Interpreter, _: Vec<Value>) -> Result<Value> {
+    Ok(Value::Number(OrderedFloat((interpreter.clock)()?)))
+}
+
+/// The default time source for `clock`. Targets without a system clock, like
+/// `wasm32-unknown-unknown`, need to supply their own with `Interpreter::set_clock`.
+pub fn system_clock() -> Result<f64> {
+    Ok(time::SystemTime::now()
+        .duration_since(time::UNIX_EPOCH)?
+        .as_secs_f64())
+}
+
+/// Returns a timer handle, the current time in seconds, for use with `elapsed`. Like
+/// `clock`, it reads the interpreter's time source.
+fn timer_start(interpreter: &mut Interpreter, _: Vec<Value>) -> Result<Value> {
+    Ok(Value::Number(OrderedFloat((interpreter.clock)()?)))
+}
+
+/// Returns the seconds passed since the timer handle was returned by `timerStart`.
+fn elapsed(interpreter: &mut Interpreter, arguments: Vec<Value>) -> Result<Value> {
+    let at = interpreter.call_site();
+    let start = number("elapsed", &arguments[0], &at)?;
+    let now = (interpreter.clock)()?;
+    Ok(Value::Number(OrderedFloat(now - start)))
+}
+
+/// `write(x)` prints `x` like `print`, but without a trailing newline.
+fn write(interpreter: &mut Interpreter, arguments: Vec<Value>) -> Result<Value> {
+    let at = interpreter.call_site();
+    let value = interpreter.call_to_string(arguments[0].clone(), &at)?;
+    write!(interpreter.output, "{value}")?;
+    // Flushed right away, since stdout otherwise holds back a partial line
+    interpreter.output.flush()?;
+    Ok(Value::Nil)
+}
+
+/// Reads a line from stdin, or the input the host set instead, without its trailing
+/// newline. Gives nil at EOF.
+fn read_line(interpreter: &mut Interpreter, _: Vec<Value>) -> Result<Value> {
+    let mut line = String::new();
+    let read = match &mut interpreter.input {
+        Some(input) => input.read_line(&mut line)?,
+        None => io::stdin().read_line(&mut line)?,
+    };
+    if read == 0 {
+        return Ok(Value::Nil);
+    }
+
+    if line.ends_with('\n') {
+        line.pop();
+        if line.ends_with('\r') {
+            line.pop();
+        }
+    }
+    Ok(Value::String(line))
+}
+
+/// Lists the names defined in the global scope, in alphabetical order.
+fn global_names(interpreter: &mut Interpreter, _: Vec<Value>) -> Result<Value> {
+    let mut names = interpreter
+        .globals
+        .names()
+        .iter()
+        .map(|name| name.to_string())
+        .collect::<Vec<_>>();
+    names.sort();
+    Ok(Value::list(names.into_iter().map(Value::String).collect()))
+}
+
+fn describe(interpreter: &mut Interpreter, arguments: Vec<Value>) -> Result<Value> {
+    let at = interpreter.call_site();
+    match &arguments[0] {
+        Value::Function(function) => Ok(Value::String(format!(
+            "<fn {} defined at line {}, arity {}>",
+            function.declaration.name.get_lexeme(),
+            function.line(),
+            function.arity()
+        ))),
+        Value::NativeFunction(function) => Ok(Value::String(format!(
+            "<native fn {}, arity {}>",
+            function.name,
+            function.arity()
+        ))),
+        value => invalid_argument_error("describe", &["Function"], value.clone(), &at),
+    }
+}
+
+fn get_class(interpreter: &mut Interpreter, arguments: Vec<Value>) -> Result<Value> {
+    let at = interpreter.call_site();
+    match &arguments[0] {
+        Value::Instance(instance) => Ok(Value::Class(instance.class().clone())),
+        value => invalid_argument_error("getClass", &["Instance"], value.clone(), &at),
+    }
+}
+
+fn has_field(interpreter: &mut Interpreter, arguments: Vec<Value>) -> Result<Value> {
+    let at = interpreter.call_site();
+    let name = string("has_field", &arguments[1], &at)?;
+    match &arguments[0] {
+        Value::Instance(instance) => Ok(Value::Bool(instance.has_field(name))),
+        value => invalid_argument_error("has_field", &["Instance"], value.clone(), &at),
+    }
+}
+
+fn delete_field(interpreter: &mut Interpreter, arguments: Vec<Value>) -> Result<Value> {
+    let at = interpreter.call_site();
+    let name = string("delete_field", &arguments[1], &at)?;
+    match &arguments[0] {
+        Value::Instance(instance) => Ok(Value::Bool(instance.clone().delete_field(name))),
+        value => invalid_argument_error("delete_field", &["Instance"], value.clone(), &at),
+    }
+}
+
+fn reverse(interpreter: &mut Interpreter, arguments: Vec<Value>) -> Result<Value> {
+    let at = interpreter.call_site();
+    match &arguments[0] {
+        Value::List(list) => Ok(Value::list(list.borrow().iter().rev().cloned().collect())),
+        Value::String(s) => Ok(Value::String(s.chars().rev().collect())),
+        value => invalid_argument_error("reverse", &["List", "String"], value.clone(), &at),
+    }
+}
+
+fn rotate(interpreter: &mut Interpreter, arguments: Vec<Value>) -> Result<Value> {
+    let at = interpreter.call_site();
+    let list = match &arguments[0] {
+        Value::List(list) => list.borrow().clone(),
+        value => return invalid_argument_error("rotate", &["List"], value.clone(), &at),
+    };
+    let n = integer("rotate", &arguments[1], &at)?;
+
+    let mut rotated = list;
+    if !rotated.is_empty() {
+        // Negative amounts rotate right, which is a left rotation modulo the length
+        let amount = n.rem_euclid(rotated.len() as i64) as usize;
+        rotated.rotate_left(amount);
+    }
+    Ok(Value::list(rotated))
+}
+
+fn len(interpreter: &mut Interpreter, arguments: Vec<Value>) -> Result<Value> {
+    let at = interpreter.call_site();
+    let len = match &arguments[0] {
+        Value::String(s) => s.chars().count(),
+        Value::List(list) => list.borrow().len(),
+        Value::Map(map) => map.len(),
+        value => {
+            return invalid_argument_error("len", &["String", "List", "Map"], value.clone(), &at)
+        }
+    };
+    Ok(Value::Number(OrderedFloat(len as f64)))
+}
+
+fn zip(interpreter: &mut Interpreter, arguments: Vec<Value>) -> Result<Value> {
+    let at = interpreter.call_site();
+    match (&arguments[0], &arguments[1]) {
+        (Value::List(left), Value::List(right)) => Ok(Value::list(
+            left.borrow()
+                .iter()
+                .zip(right.borrow().iter())
+                .map(|(l, r)| Value::list(vec![l.clone(), r.clone()]))
+                .collect(),
+        )),
+        (Value::List(_), value) | (value, _) => {
+            invalid_argument_error("zip", &["List"], value.clone(), &at)
+        }
+    }
+}
+
+fn enumerate(interpreter: &mut Interpreter, arguments: Vec<Value>) -> Result<Value> {
+    let at = interpreter.call_site();
+    match &arguments[0] {
+        Value::List(list) => Ok(Value::list(
+            list.borrow()
+                .iter()
+                .enumerate()
+                .map(|(i, value)| {
+                    Value::list(vec![Value::Number(OrderedFloat(i as f64)), value.clone()])
+                })
+                .collect(),
+        )),
+        value => invalid_argument_error("enumerate", &["List"], value.clone(), &at),
+    }
+}
+
+/// `push(list, value)` appends `value` to the end of `list` in place and returns nil.
+fn push(interpreter: &mut Interpreter, arguments: Vec<Value>) -> Result<Value> {
+    let at = interpreter.call_site();
+    match &arguments[0] {
+        Value::List(list) => {
+            list.borrow_mut().push(arguments[1].clone());
+            Ok(Value::Nil)
+        }
+        value => invalid_argument_error("push", &["List"], value.clone(), &at),
+    }
+}
+
+/// `pop(list)` removes the last element of `list` in place and returns it.
+fn pop(interpreter: &mut Interpreter, arguments: Vec<Value>) -> Result<Value> {
+    let at = interpreter.call_site();
+    match &arguments[0] {
+        Value::List(list) => list.borrow_mut().pop().ok_or(Error::EmptyList {
+            function: "pop".to_string(),
+            line: at.get_line(),
+            column: at.get_column(),
+        }),
+        value => invalid_argument_error("pop", &["List"], value.clone(), &at),
+    }
+}
+
+/// `insert(list, index, value)` inserts `value` at `index` in place, shifting later
+/// elements right, and returns nil. `index` may be equal to the length to append.
+fn insert(interpreter: &mut Interpreter, arguments: Vec<Value>) -> Result<Value> {
+    let at = interpreter.call_site();
+    match &arguments[0] {
+        Value::List(list) => {
+            let mut list = list.borrow_mut();
+            let index = checked_bound(&arguments[1], list.len(), &at)?;
+            list.insert(index, arguments[2].clone());
+            Ok(Value::Nil)
+        }
+        value => invalid_argument_error("insert", &["List"], value.clone(), &at),
+    }
+}
+
+/// `keys(map)` returns a list of the keys of `map`, in insertion order.
+fn keys(interpreter: &mut Interpreter, arguments: Vec<Value>) -> Result<Value> {
+    let at = interpreter.call_site();
+    let map = map("keys", &arguments[0], &at)?;
+    let keys = map.entries().into_iter().map(|(key, _)| key).collect();
+    Ok(Value::list(keys))
+}
+
+/// `values(map)` returns a list of the values of `map`, in the order of their keys.
+fn values(interpreter: &mut Interpreter, arguments: Vec<Value>) -> Result<Value> {
+    let at = interpreter.call_site();
+    let map = map("values", &arguments[0], &at)?;
+    let values = map.entries().into_iter().map(|(_, value)| value).collect();
+    Ok(Value::list(values))
+}
+
+/// `contains(map, key)` checks whether `map` has an entry for `key`.
+fn contains(interpreter: &mut Interpreter, arguments: Vec<Value>) -> Result<Value> {
+    let at = interpreter.call_site();
+    let map = map("contains", &arguments[0], &at)?;
+    let found = map.get(&arguments[1], interpreter, &at)?.is_some();
+    Ok(Value::Bool(found))
+}
+
+/// Negative numbers produce NaN rather than an error, following IEEE semantics.
+fn sqrt(interpreter: &mut Interpreter, arguments: Vec<Value>) -> Result<Value> {
+    let at = interpreter.call_site();
+    let n = number("sqrt", &arguments[0], &at)?;
+    Ok(Value::Number(OrderedFloat(n.sqrt())))
+}
+
+fn floor(interpreter: &mut Interpreter, arguments: Vec<Value>) -> Result<Value> {
+    let at = interpreter.call_site();
+    let n = number("floor", &arguments[0], &at)?;
+    Ok(Value::Number(OrderedFloat(n.floor())))
+}
+
+fn ceil(interpreter: &mut Interpreter, arguments: Vec<Value>) -> Result<Value> {
+    let at = interpreter.call_site();
+    let n = number("ceil", &arguments[0], &at)?;
+    Ok(Value::Number(OrderedFloat(n.ceil())))
+}
+
+fn abs(interpreter: &mut Interpreter, arguments: Vec<Value>) -> Result<Value> {
+    let at = interpreter.call_site();
+    let n = number("abs", &arguments[0], &at)?;
+    Ok(Value::Number(OrderedFloat(n.abs())))
+}
+
+/// `round(n)` rounds to the nearest whole number, and `round(n, places)` to that many
+/// decimal places, where negative places round to tens, hundreds, and so on. Halfway values
+/// round away from zero (half-up in magnitude), so `round(2.5)` is 3 and `round(-2.5)` is -3.
+fn round(interpreter: &mut Interpreter, arguments: Vec<Value>) -> Result<Value> {
+    let at = interpreter.call_site();
+    let n = number("round", &arguments[0], &at)?;
+    let places = match arguments.get(1) {
+        Some(places) => integer("round", places, &at)?,
+        None => 0,
+    };
+
+    // 10^308 is the largest finite power of ten, and rounding to more places than that
+    // can't change the result
+    let factor = 10f64.powi(places.unsigned_abs().min(308) as i32);
+    let rounded = if places >= 0 {
+        (n * factor).round() / factor
+    } else {
+        // Divide rather than multiplying by a fraction, which isn't exact for powers of ten
+        (n / factor).round() * factor
+    };
+
+    // Scaling a number that's already precise enough can overflow, so leave it as is
+    Ok(Value::from(if rounded.is_finite() { rounded } else { n }))
+}
+
+fn pow(interpreter: &mut Interpreter, arguments: Vec<Value>) -> Result<Value> {
+    let at = interpreter.call_site();
+    let base = number("pow", &arguments[0], &at)?;
+    let exponent = number("pow", &arguments[1], &at)?;
+    Ok(Value::Number(OrderedFloat(base.powf(exponent))))
+}
+
+/// `min(a, b, ...)` returns the smallest of its arguments, or `min(list)` the smallest in a list.
+fn min(interpreter: &mut Interpreter, arguments: Vec<Value>) -> Result<Value> {
+    let at = interpreter.call_site();
+    extremum("min", arguments, f64::min, &at)
+}
+
+/// `max(a, b, ...)` returns the largest of its arguments, or `max(list)` the largest in a list.
+fn max(interpreter: &mut Interpreter, arguments: Vec<Value>) -> Result<Value> {
+    let at = interpreter.call_site();
+    extremum("max", arguments, f64::max, &at)
+}
+
+/// Reduces the number arguments, or the numbers in a single list argument, with `pick`.
+fn extremum(
+    function: &str,
+    arguments: Vec<Value>,
+    pick: fn(f64, f64) -> f64,
+    at: &Token,
+) -> Result<Value> {
+    let values = match arguments.as_slice() {
+        [Value::List(list)] => list.borrow().clone(),
+        [value] => return invalid_argument_error(function, &["List"], value.clone(), at),
+        _ => arguments,
+    };
+
+    let numbers = (values.iter())
+        .map(|value| number(function, value, at))
+        .collect::<Result<Vec<_>>>()?;
+    numbers
+        .into_iter()
+        .reduce(pick)
+        .map(Value::from)
+        .ok_or(Error::EmptyList {
+            function: function.to_string(),
+            line: at.get_line(),
+            column: at.get_column(),
+        })
+}
+
+/// `clamp(x, lo, hi)` limits `x` to the range from `lo` to `hi`, inclusive.
+fn clamp(interpreter: &mut Interpreter, arguments: Vec<Value>) -> Result<Value> {
+    let at = interpreter.call_site();
+    let x = number("clamp", &arguments[0], &at)?;
+    let lo = number("clamp", &arguments[1], &at)?;
+    let hi = number("clamp", &arguments[2], &at)?;
+    if lo > hi {
+        return Err(Error::InvalidClampBounds {
+            lo,
+            hi,
+            line: at.get_line(),
+            column: at.get_column(),
+        });
+    }
+    // Unlike `f64::clamp`, this doesn't panic when a bound is NaN
+    Ok(Value::from(x.max(lo).min(hi)))
+}
+
+/// The largest integer `n` such that `n` and every integer below it is exactly representable
+/// as an `f64`, i.e. 2^53 - 1.
+const MAX_SAFE_INTEGER: f64 = 9_007_199_254_740_991.0;
+
+/// `isSafeInteger(n)` checks that `n` is a whole number that hasn't lost precision. Past
+/// 2^53, neighbouring integers round to the same number, so arithmetic on them is inexact.
+fn is_safe_integer(interpreter: &mut Interpreter, arguments: Vec<Value>) -> Result<Value> {
+    let at = interpreter.call_site();
+    let n = number("isSafeInteger", &arguments[0], &at)?;
+    Ok(Value::Bool(n.fract() == 0.0 && n.abs() <= MAX_SAFE_INTEGER))
+}
+
+/// Converts a value to a string as `print` shows it, calling an instance's `to_string` method.
+fn to_string(interpreter: &mut Interpreter, arguments: Vec<Value>) -> Result<Value> {
+    let at = interpreter.call_site();
+    let value = interpreter.call_to_string(arguments[0].clone(), &at)?;
+    Ok(Value::String(value.to_string()))
+}
+
+/// Parses a number literal as the scanner accepts it, optionally negated and surrounded
+/// by whitespace. Returns nil if the string isn't numeric.
+fn to_number(interpreter: &mut Interpreter, arguments: Vec<Value>) -> Result<Value> {
+    let at = interpreter.call_site();
+    let s = match &arguments[0] {
+        Value::String(s) => s.trim(),
+        value => return invalid_argument_error("to_number", &["String"], value.clone(), &at),
+    };
+
+    let digits = s.strip_prefix('-').unwrap_or(s);
+    let (integer, fraction) = digits.split_once('.').unwrap_or((digits, "0"));
+    let is_numeric = |part: &str| !part.is_empty() && part.bytes().all(|c| c.is_ascii_digit());
+
+    if is_numeric(integer) && is_numeric(fraction) {
+        Ok(s.parse::<f64>()
+            .map_or(Value::Nil, |n| Value::Number(OrderedFloat(n))))
+    } else {
+        Ok(Value::Nil)
+    }
+}
+
+/// Returns the same type name that error messages report for the value.
+fn type_of(_: &mut Interpreter, arguments: Vec<Value>) -> Result<Value> {
+    Ok(Value::String(arguments[0].type_of().to_string()))
+}
+
+/// `substring(s, start, end)` returns the characters from `start` up to but not including `end`.
+fn substring(interpreter: &mut Interpreter, arguments: Vec<Value>) -> Result<Value> {
+    let at = interpreter.call_site();
+    let s = string("substring", &arguments[0], &at)?;
+    let len = s.chars().count();
+    let start = checked_bound(&arguments[1], len, &at)?;
+    let end = checked_bound(&arguments[2], len, &at)?;
+
+    if start > end {
+        return Err(Error::InvalidRange {
+            start,
+            end,
+            line: at.get_line(),
+            column: at.get_column(),
+        });
+    }
+    Ok(Value::String(
+        s.chars().skip(start).take(end - start).collect(),
+    ))
+}
+
+/// `index_of(s, needle)` returns the character index of the first occurrence of `needle`, or -1.
+fn index_of(interpreter: &mut Interpreter, arguments: Vec<Value>) -> Result<Value> {
+    let at = interpreter.call_site();
+    let s = string("index_of", &arguments[0], &at)?;
+    let needle = string("index_of", &arguments[1], &at)?;
+
+    let index = s
+        .find(needle)
+        .map_or(-1.0, |byte| s[..byte].chars().count() as f64);
+    Ok(Value::Number(OrderedFloat(index)))
+}
+
+fn to_upper(interpreter: &mut Interpreter, arguments: Vec<Value>) -> Result<Value> {
+    let at = interpreter.call_site();
+    Ok(Value::String(
+        string("to_upper", &arguments[0], &at)?.to_uppercase(),
+    ))
+}
+
+fn to_lower(interpreter: &mut Interpreter, arguments: Vec<Value>) -> Result<Value> {
+    let at = interpreter.call_site();
+    Ok(Value::String(
+        string("to_lower", &arguments[0], &at)?.to_lowercase(),
+    ))
+}
+
+/// `split(s, sep)` splits `s` on every occurrence of `sep`, keeping empty parts between
+/// consecutive separators. An empty separator splits `s` into its characters.
+fn split(interpreter: &mut Interpreter, arguments: Vec<Value>) -> Result<Value> {
+    let at = interpreter.call_site();
+    let s = string("split", &arguments[0], &at)?;
+    let separator = string("split", &arguments[1], &at)?;
+
+    let parts = if separator.is_empty() {
+        s.chars().map(|c| Value::String(c.to_string())).collect()
+    } else {
+        s.split(separator)
+            .map(|part| Value::String(part.to_string()))
+            .collect()
+    };
+    Ok(Value::list(parts))
+}
+
+/// `join(list, sep)` concatenates the display form of each element, separated by `sep`.
+fn join(interpreter: &mut Interpreter, arguments: Vec<Value>) -> Result<Value> {
+    let at = interpreter.call_site();
+    let separator = string("join", &arguments[1], &at)?;
+    match &arguments[0] {
+        Value::List(list) => Ok(Value::String(
+            list.borrow()
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>()
+                .join(separator),
+        )),
+        value => invalid_argument_error("join", &["List"], value.clone(), &at),
+    }
+}
+
+/// `formatNumber(n, decimals, grouping, width)` formats `n` with `decimals` fixed decimal
+/// places (or as many as needed if nil), commas between thousands if `grouping` is true,
+/// and zeros after the sign to pad it to at least `width` characters.
+fn format_number(interpreter: &mut Interpreter, arguments: Vec<Value>) -> Result<Value> {
+    let at = interpreter.call_site();
+    let n = number("formatNumber", &arguments[0], &at)?;
+    let decimals = match &arguments[1] {
+        Value::Nil => None,
+        value => match integer("formatNumber", value, &at)? {
+            d if d >= 0 => Some(d as usize),
+            _ => {
+                return invalid_argument_error(
+                    "formatNumber",
+                    &["Nil", "Integer"],
+                    value.clone(),
+                    &at,
+                )
+            }
+        },
+    };
+    let grouping = match &arguments[2] {
+        Value::Bool(b) => *b,
+        value => return invalid_argument_error("formatNumber", &["Bool"], value.clone(), &at),
+    };
+    let width = integer("formatNumber", &arguments[3], &at)?.max(0) as usize;
+
+    if !n.is_finite() {
+        return Ok(Value::String(n.to_string()));
+    }
+
+    let digits = match decimals {
+        Some(decimals) => format!("{:.*}", decimals, n.abs()),
+        None => n.abs().to_string(),
+    };
+    let (integer_part, fraction) = match digits.split_once('.') {
+        Some((integer_part, fraction)) => (integer_part, format!(".{fraction}")),
+        None => (digits.as_str(), String::new()),
+    };
+
+    let mut body = String::new();
+    for (i, c) in integer_part.chars().enumerate() {
+        // Separate each group of three digits counting from the decimal point
+        if grouping && i > 0 && (integer_part.len() - i) % 3 == 0 {
+            body.push(',');
+        }
+        body.push(c);
+    }
+    body.push_str(&fraction);
+
+    let sign = if n.is_sign_negative() && n != 0.0 {
+        "-"
+    } else {
+        ""
+    };
+    let padding = "0".repeat(width.saturating_sub(sign.len() + body.len()));
+    Ok(Value::String(format!("{sign}{padding}{body}")))
+}
+
+/// `words(s)` splits `s` on runs of whitespace, dropping empty words.
+fn words(interpreter: &mut Interpreter, arguments: Vec<Value>) -> Result<Value> {
+    let at = interpreter.call_site();
+    let s = string("words", &arguments[0], &at)?;
+    Ok(Value::list(
+        s.split_whitespace()
+            .map(|word| Value::String(word.to_string()))
+            .collect(),
+    ))
+}
+
+/// `lines(s)` splits `s` on `\n` or `\r\n` without the line endings. A trailing newline
+/// ends the last line rather than starting an empty one.
+fn lines(interpreter: &mut Interpreter, arguments: Vec<Value>) -> Result<Value> {
+    let at = interpreter.call_site();
+    let s = string("lines", &arguments[0], &at)?;
+    Ok(Value::list(
+        s.lines()
+            .map(|line| Value::String(line.to_string()))
+            .collect(),
+    ))
+}
+
+/// `assert(condition)` or `assert(condition, message)` raises an error if the condition is falsey.
+fn assert(interpreter: &mut Interpreter, arguments: Vec<Value>) -> Result<Value> {
+    if arguments[0].is_truthy() {
+        return Ok(Value::Nil);
+    }
+
+    let at = interpreter.call_site();
+    let message = match arguments.get(1) {
+        Some(message) => Some(
+            interpreter
+                .call_to_string(message.clone(), &at)?
+                .to_string(),
+        ),
+        None => None,
+    };
+    Err(Error::AssertionFailed {
+        message,
+        line: at.get_line(),
+        column: at.get_column(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::loxide::test_support::{error, output, session};
+
+    /// The kind of the error a program fails with, looking through tracebacks.
+    fn kind(source: &str) -> &'static str {
+        error(source).diagnostics()[0].kind
+    }
+
+    #[test]
+    fn describe_gives_where_a_function_was_defined() {
+        let source = "
+            fn greet(name) {
+                print name;
+            }
+            print describe(greet);
+            print describe(clock);
+        ";
+        assert_eq!(
+            output(source),
+            "<fn greet defined at line 2, arity 1>\n<native fn clock, arity 0>\n"
+        );
+    }
+
+    #[test]
+    fn get_class_returns_a_class_that_makes_siblings() {
+        let source = "
+            class Point {}
+            var p = Point();
+            var Class = getClass(p);
+            var q = Class();
+            print Class == Point;
+            print getClass(q) == getClass(p);
+            print q;
+        ";
+        assert_eq!(output(source), "true\ntrue\n<instance of Point>\n");
+        assert_eq!(kind("getClass(1);"), "InvalidArgument");
+    }
+
+    #[test]
+    fn reverse_and_rotate_return_copies() {
+        let source = r#"
+            var list = [1, 2, 3, 4];
+            print reverse(list);
+            print reverse("héllo");
+            print rotate(list, 1);
+            print rotate(list, -1);
+            print rotate(list, 6);
+            print list;
+        "#;
+        assert_eq!(
+            output(source),
+            "[4, 3, 2, 1]\nolléh\n[2, 3, 4, 1]\n[4, 1, 2, 3]\n[3, 4, 1, 2]\n[1, 2, 3, 4]\n"
+        );
+        assert_eq!(kind("reverse(1);"), "InvalidArgument");
+        assert_eq!(kind("rotate(\"ab\", 1);"), "InvalidArgument");
+        assert_eq!(kind("rotate([1], 0.5);"), "InvalidArgument");
+    }
+
+    #[test]
+    fn len_counts_characters_and_elements() {
+        let source = r#"
+            print len("");
+            print len("日本語");
+            print len([]);
+            print len([[1, 2], [3]]);
+        "#;
+        assert_eq!(output(source), "0\n3\n0\n2\n");
+        assert_eq!(kind("len(nil);"), "InvalidArgument");
+    }
+
+    #[test]
+    fn zip_stops_at_the_shorter_list_and_enumerate_counts_from_zero() {
+        let source = r#"
+            print zip([1, 2, 3], ["a", "b"]);
+            print enumerate(["x", "y", "z"]);
+        "#;
+        assert_eq!(
+            output(source),
+            "[[1, a], [2, b]]\n[[0, x], [1, y], [2, z]]\n"
+        );
+        assert_eq!(kind("zip([], nil);"), "InvalidArgument");
+        assert_eq!(kind("enumerate(\"ab\");"), "InvalidArgument");
+    }
+
+    #[test]
+    fn math_natives_round_towards_the_right_side() {
+        let source = "
+            print sqrt(16);
+            print sqrt(-1);
+            print floor(-1.5);
+            print ceil(-1.5);
+            print abs(-2);
+            print pow(2, 10) == 1024;
+        ";
+        assert_eq!(output(source), "4\nNaN\n-2\n-1\n2\ntrue\n");
+        assert_eq!(kind("sqrt(\"4\");"), "InvalidArgument");
+    }
+
+    #[test]
+    fn to_string_and_to_number_round_trip() {
+        let source = r#"
+            print to_string(12) + "!";
+            print to_number(" 3.5 ") + 1;
+            print to_number(to_string(-42)) == -42;
+            print to_string([1, "a"]);
+            print to_number("x");
+            print to_number("1e3");
+            print to_number("1.");
+            print to_number("");
+        "#;
+        assert_eq!(
+            output(source),
+            "12!\n4.5\ntrue\n[1, a]\nnil\nnil\nnil\nnil\n"
+        );
+        assert_eq!(kind("to_number(1);"), "InvalidArgument");
+    }
+
+    #[test]
+    fn format_number_groups_fixes_decimals_and_pads() {
+        let source = "
+            print formatNumber(1234567, nil, true, 0);
+            print formatNumber(-1234567.891, 1, true, 0);
+            print formatNumber(1234.5, 2, false, 0);
+            print formatNumber(42, nil, false, 6);
+            print formatNumber(-42.5, 2, false, 8);
+        ";
+        assert_eq!(
+            output(source),
+            "1,234,567\n-1,234,567.9\n1234.50\n000042\n-0042.50\n"
+        );
+        assert_eq!(kind("formatNumber(1, -1, false, 0);"), "InvalidArgument");
+        assert_eq!(kind("formatNumber(1, nil, 1, 0);"), "InvalidArgument");
+    }
+
+    #[test]
+    fn min_max_and_clamp_handle_ties_and_negatives() {
+        let source = "
+            print min(-1, -1);
+            print max(-3, -2);
+            print min([4, -5, 2]);
+            print max([7]);
+            print clamp(2, 2, 2);
+            print clamp(-5, -3, -1);
+            print clamp(0, -3, -1);
+        ";
+        assert_eq!(output(source), "-1\n-2\n-5\n7\n2\n-3\n-1\n");
+        assert_eq!(kind("clamp(1, 3, 0);"), "InvalidClampBounds");
+        assert_eq!(kind("min([]);"), "EmptyList");
+        assert_eq!(kind("max(2);"), "InvalidArgument");
+        assert_eq!(kind("min(1, \"a\");"), "InvalidArgument");
+    }
+
+    #[test]
+    fn words_collapse_whitespace_and_lines_drop_line_endings() {
+        let source = "
+            print words(\"  one   two\tthree \");
+            print len(words(\"   \"));
+            print lines(\"a\nb\n\");
+            print lines(\"a\r\n\nb\");
+            print len(lines(\"\"));
+            print len(lines(\"\n\"));
+        ";
+        assert_eq!(
+            output(source),
+            "[one, two, three]\n0\n[a, b]\n[a, , b]\n0\n1\n"
+        );
+        assert_eq!(kind("words(1);"), "InvalidArgument");
+        assert_eq!(kind("lines(nil);"), "InvalidArgument");
+    }
+
+    #[test]
+    fn split_keeps_empty_parts_and_join_reverses_it() {
+        let source = r#"
+            print split("a,,b", ",");
+            print split(",a,", ",");
+            print split("", ",") == [""];
+            print split("héy", "");
+            print len(split("", ""));
+            print join(split("a--b", "-"), "-");
+            print join([], ",") == "";
+            print join([1, nil, true], " ");
+        "#;
+        assert_eq!(
+            output(source),
+            "[a, , b]\n[, a, ]\ntrue\n[h, é, y]\n0\na--b\ntrue\n1 nil true\n"
+        );
+        assert_eq!(kind("split(\"a\", 1);"), "InvalidArgument");
+        assert_eq!(kind("join(\"ab\", \",\");"), "InvalidArgument");
+    }
+
+    #[test]
+    fn write_and_to_string_show_values_like_print() {
+        let source = r#"
+            class Point {
+                init(x) { this.x = x; }
+                to_string() { return "Point(" + to_string(this.x) + ")"; }
+            }
+            write(1);
+            write("a");
+            write(Point(2));
+            print "";
+            print to_string(Point(3)) == "Point(3)";
+            print to_string([1, nil]);
+        "#;
+        assert_eq!(output(source), "1aPoint(2)\ntrue\n[1, nil]\n");
+
+        let failing = "class Bad { to_string() { return missing; } }";
+        assert_eq!(
+            kind(&format!("{failing} write(Bad());")),
+            "UndefinedVariable"
+        );
+        assert_eq!(
+            kind(&format!("{failing} to_string(Bad());")),
+            "UndefinedVariable"
+        );
+        assert_eq!(kind("write();"), "InvalidArgumentCount");
+    }
+
+    #[test]
+    fn push_pop_and_insert_change_lists_in_place() {
+        let source = "
+            var xs = [1];
+            print push(xs, 2);
+            print xs;
+            print pop(xs);
+            print xs;
+            insert(xs, 0, 0);
+            insert(xs, 2, 9);
+            print xs;
+        ";
+        assert_eq!(output(source), "nil\n[1, 2]\n2\n[1]\n[0, 1, 9]\n");
+
+        assert_eq!(kind("pop([]);"), "EmptyList");
+        // Inserting at the length appends, but not past it
+        assert_eq!(kind("insert([1], 3, 0);"), "IndexOutOfBounds");
+        assert_eq!(kind("insert([1], -1, 0);"), "IndexOutOfBounds");
+        assert_eq!(kind("insert([1], 0.5, 0);"), "InvalidIndex");
+        assert_eq!(kind("push(1, 2);"), "InvalidArgument");
+        assert_eq!(kind("pop(\"a\");"), "InvalidArgument");
+    }
+
+    #[test]
+    fn string_natives_count_characters_not_bytes() {
+        let source = r#"
+            print substring("héllo", 1, 3);
+            print substring("abc", 0, 3);
+            print substring("abc", 2, 2) == "";
+            print index_of("naïve café", "café");
+            print index_of("abc", "z");
+            print index_of("abc", "");
+            print to_upper("straße");
+            print to_lower("ÀBC");
+        "#;
+        assert_eq!(output(source), "él\nabc\ntrue\n6\n-1\n0\nSTRASSE\nàbc\n");
+
+        assert_eq!(kind("substring(\"abc\", 2, 4);"), "IndexOutOfBounds");
+        assert_eq!(kind("substring(\"abc\", -1, 1);"), "IndexOutOfBounds");
+        assert_eq!(kind("substring(\"abc\", 2, 1);"), "InvalidRange");
+        assert_eq!(kind("substring(\"abc\", 0.5, 1);"), "InvalidIndex");
+        assert_eq!(kind("index_of(1, \"a\");"), "InvalidArgument");
+        assert_eq!(kind("to_upper(nil);"), "InvalidArgument");
+    }
+
+    #[test]
+    fn round_takes_halves_away_from_zero_at_any_number_of_places() {
+        let source = "
+            print round(3.14159);
+            print round(3.14159, 2);
+            print round(2.5);
+            print round(-2.5);
+            print round(-3.7);
+            print round(0.125, 2);
+            print round(1250, -2);
+            print round(1.5, 400);
+        ";
+        assert_eq!(output(source), "3\n3.14\n3\n-3\n-4\n0.13\n1300\n1.5\n");
+
+        assert_eq!(kind("round(\"1\");"), "InvalidArgument");
+        assert_eq!(kind("round(1, 0.5);"), "InvalidArgument");
+        assert_eq!(kind("round(1, 2, 3);"), "InvalidArgumentCount");
+    }
+
+    #[test]
+    fn is_safe_integer_stops_where_numbers_lose_precision() {
+        let source = "
+            print isSafeInteger(9007199254740991);
+            print isSafeInteger(-9007199254740991);
+            print isSafeInteger(9007199254740992);
+            print isSafeInteger(1.5);
+            print isSafeInteger(0);
+            print 9007199254740993;
+        ";
+        // The last literal can't be represented, so it reads as its neighbour
+        assert_eq!(
+            output(source),
+            "true\ntrue\nfalse\nfalse\ntrue\n9007199254740992\n"
+        );
+
+        assert_eq!(kind("isSafeInteger(\"1\");"), "InvalidArgument");
+        assert_eq!(kind("isSafeInteger();"), "InvalidArgumentCount");
+    }
+
+    #[test]
+    fn fields_can_be_checked_for_and_deleted() {
+        let source = r#"
+            class A { init() { this.x = 1; } method() {} }
+            var a = A();
+            print has_field(a, "x");
+            print has_field(a, "method");
+            print delete_field(a, "x");
+            print has_field(a, "x");
+            print delete_field(a, "x");
+        "#;
+        // Methods aren't fields, and deleting reports whether there was one
+        assert_eq!(output(source), "true\nfalse\ntrue\nfalse\nfalse\n");
+
+        let deleted = "class A { init() { this.x = 1; } } var a = A(); delete_field(a, \"x\");";
+        assert_eq!(kind(&format!("{deleted} print a.x;")), "UndefinedProperty");
+        assert_eq!(kind("has_field(1, \"x\");"), "InvalidArgument");
+        assert_eq!(kind("class A {} has_field(A(), 1);"), "InvalidArgument");
+        assert_eq!(kind("delete_field(nil, \"x\");"), "InvalidArgument");
+    }
+
+    #[test]
+    fn globals_lists_the_names_defined_so_far() {
+        let source = r#"
+            fn has(names, name) { for (n in names) if (n == name) return true; return false; }
+            var before = globals();
+            print has(before, "clock") and has(before, "has") and !has(before, "mine");
+            var mine = 1;
+            var after = globals();
+            print len(after) - len(before);
+            print has(after, "mine");
+            { var local = 1; print has(globals(), "local"); }
+        "#;
+        // Only globals are listed, and `after` sees `before` as well as `mine`
+        assert_eq!(output(source), "true\n2\ntrue\nfalse\n");
+
+        assert_eq!(kind("globals(1);"), "InvalidArgumentCount");
+    }
+
+    #[test]
+    fn read_line_strips_line_endings_and_gives_nil_at_the_end() {
+        let (mut loxide, printed) = session();
+        loxide.set_input(Box::new(&b"first\r\n\nlast"[..]));
+        let source = "
+            var line = read_line();
+            while (line != nil) { print \"[\" + line + \"]\"; line = read_line(); }
+            print read_line();
+        ";
+        loxide.eval(source).unwrap();
+        assert_eq!(printed.text(), "[first]\n[]\n[last]\nnil\n");
+
+        // Input that isn't UTF-8 can't be a string
+        loxide.set_input(Box::new(&b"\xff\n"[..]));
+        let error = loxide.eval("read_line();").unwrap_err();
+        assert_eq!(error.diagnostics()[0].kind, "Io");
+
+        assert_eq!(kind("read_line(1);"), "InvalidArgumentCount");
+    }
+}
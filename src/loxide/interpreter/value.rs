@@ -1,42 +1,119 @@
-use std::fmt;
+use std::{cell::RefCell, fmt, rc::Rc};
 
 use ordered_float::OrderedFloat;
 
-use crate::loxide::ast::Literal;
+use crate::loxide::{ast::Literal, token::Token};
 
 use super::{
     classes::{Class, Instance},
     functions::{Function, NativeFunction},
-    Error,
+    map::Map,
+    Error, Interpreter, Result,
 };
 
+/// Every name `Value::type_of` gives, which are also the types a `match` arm can test for.
+pub const TYPE_NAMES: [&str; 9] = [
+    "Nil", "Number", "Bool", "String", "List", "Map", "Function", "Class", "Instance",
+];
+
 #[derive(Debug, Clone)]
 pub enum Value {
     Nil,
+    /// Every number is a 64-bit float, there's no separate integer type. Whole numbers print
+    /// without a fractional part, so `10 / 2` prints `5` and `7 / 2` prints `3.5`. Integers
+    /// are only exact up to 2^53 in magnitude, which `isSafeInteger` checks.
     Number(OrderedFloat<f64>),
     Bool(bool),
     String(String),
-    NativeFunction(NativeFunction),
+    List(Rc<RefCell<Vec<Value>>>),
+    Map(Map),
+    // Natives and classes are boxed behind a shared handle to keep every value small
+    NativeFunction(Rc<NativeFunction>),
     Function(Function),
-    Class(Class),
+    Class(Rc<Class>),
     Instance(Instance),
 }
 
 impl Value {
+    pub fn list(elements: Vec<Value>) -> Self {
+        Self::List(Rc::new(RefCell::new(elements)))
+    }
+
     pub fn is_truthy(&self) -> bool {
         !matches!(self, Self::Nil | Self::Bool(false))
     }
 
-    pub fn type_of(&self) -> String {
+    /// Compares two values with `==`. An instance whose class defines an `equals(other)`
+    /// method is compared by calling it, with the other value as the argument, and the
+    /// truthiness of the result decides equality. Lists are equal when they have the same
+    /// length and their elements are pairwise equal by the same rules, and maps when they
+    /// have the same keys with equal values, in any order. Everything else
+    /// compares as `PartialEq` does. `!=` is always the negation, so `equals` should be
+    /// symmetric. `at` is the token the call to `equals` is reported at.
+    pub fn is_equal(
+        &self,
+        other: &Value,
+        interpreter: &mut Interpreter,
+        at: &Rc<Token>,
+    ) -> Result<bool> {
+        match (self, other) {
+            (Self::Instance(instance), _) => {
+                if let Some(method) = instance.class().find_method("equals") {
+                    let method = Function::try_from(method)?.bind(instance.clone());
+                    return Ok(interpreter
+                        .call_value(&method, vec![other.clone()], at)?
+                        .is_truthy());
+                }
+            }
+            (Self::List(left), Self::List(right)) => {
+                if Rc::ptr_eq(left, right) {
+                    return Ok(true);
+                }
+                // Cloned so `equals` can modify either list while they're compared
+                let (left, right) = (left.borrow().clone(), right.borrow().clone());
+                if left.len() != right.len() {
+                    return Ok(false);
+                }
+                for (left, right) in left.iter().zip(&right) {
+                    if !left.is_equal(right, interpreter, at)? {
+                        return Ok(false);
+                    }
+                }
+                return Ok(true);
+            }
+            (Self::Map(left), Self::Map(right)) => {
+                if left.is(right) {
+                    return Ok(true);
+                }
+                if left.len() != right.len() {
+                    return Ok(false);
+                }
+                for (key, value) in left.entries() {
+                    match right.get(&key, interpreter, at)? {
+                        Some(other) if value.is_equal(&other, interpreter, at)? => {}
+                        _ => return Ok(false),
+                    }
+                }
+                return Ok(true);
+            }
+            _ => {}
+        }
+        Ok(self == other)
+    }
+
+    /// The name of the value's type, one of `TYPE_NAMES`. Natives and Lox functions are
+    /// both `Function`.
+    pub fn type_of(&self) -> &'static str {
         match self {
-            Self::Nil => String::from("Nil"),
-            Self::Number(_) => String::from("Number"),
-            Self::Bool(_) => String::from("Bool"),
-            Self::String(_) => String::from("String"),
-            Self::NativeFunction(_) => String::from("<native fn>"),
-            Self::Function(_) => String::from("<fn>"),
-            Self::Class(_) => String::from("<class>"),
-            Self::Instance(_) => String::from("<instance>"),
+            Self::Nil => "Nil",
+            Self::Number(_) => "Number",
+            Self::Bool(_) => "Bool",
+            Self::String(_) => "String",
+            Self::List(_) => "List",
+            Self::Map(_) => "Map",
+            Self::NativeFunction(_) | Self::Function(_) => "Function",
+            Self::Class(_) => "Class",
+            Self::Instance(_) => "Instance",
         }
     }
 }
@@ -54,12 +131,96 @@ impl TryFrom<&Literal> for Value {
     }
 }
 
+impl From<f64> for Value {
+    fn from(n: f64) -> Self {
+        Value::Number(OrderedFloat(n))
+    }
+}
+
+impl From<bool> for Value {
+    fn from(b: bool) -> Self {
+        Value::Bool(b)
+    }
+}
+
+impl From<String> for Value {
+    fn from(s: String) -> Self {
+        Value::String(s)
+    }
+}
+
+impl From<&str> for Value {
+    fn from(s: &str) -> Self {
+        Value::String(s.to_string())
+    }
+}
+
+impl From<Vec<Value>> for Value {
+    fn from(elements: Vec<Value>) -> Self {
+        Value::list(elements)
+    }
+}
+
+impl TryFrom<Value> for f64 {
+    type Error = Error;
+
+    fn try_from(value: Value) -> Result<Self, Self::Error> {
+        match value {
+            Value::Number(n) => Ok(n.into_inner()),
+            _ => Err(Error::ConversionError {
+                from: value,
+                to: "Number".to_string(),
+            }),
+        }
+    }
+}
+
+impl TryFrom<Value> for bool {
+    type Error = Error;
+
+    fn try_from(value: Value) -> Result<Self, Self::Error> {
+        match value {
+            Value::Bool(b) => Ok(b),
+            _ => Err(Error::ConversionError {
+                from: value,
+                to: "Bool".to_string(),
+            }),
+        }
+    }
+}
+
+impl TryFrom<Value> for String {
+    type Error = Error;
+
+    fn try_from(value: Value) -> Result<Self, Self::Error> {
+        match value {
+            Value::String(s) => Ok(s),
+            _ => Err(Error::ConversionError {
+                from: value,
+                to: "String".to_string(),
+            }),
+        }
+    }
+}
+
 impl PartialEq for Value {
     fn eq(&self, other: &Self) -> bool {
         match (self, other) {
-            (Self::Number(left), Self::Number(right)) => left == right,
+            // Numbers use IEEE equality: `NaN` is never equal to anything, itself included,
+            // and `-0.0` equals `0.0`. `OrderedFloat` would consider `NaN == NaN`, so compare
+            // the inner floats instead. The ordering operators follow the same rules.
+            (Self::Number(left), Self::Number(right)) => left.0 == right.0,
             (Self::Bool(left), Self::Bool(right)) => left == right,
             (Self::String(left), Self::String(right)) => left == right,
+            (Self::List(left), Self::List(right)) => *left.borrow() == *right.borrow(),
+            // Maps, callables, classes, and instances compare by identity. Maps and instances
+            // are only equal to themselves here, `==` in Lox also compares entries and calls
+            // `equals`
+            (Self::Map(left), Self::Map(right)) => left.is(right),
+            (Self::NativeFunction(left), Self::NativeFunction(right)) => Rc::ptr_eq(left, right),
+            (Self::Function(left), Self::Function(right)) => left.is(right),
+            (Self::Class(left), Self::Class(right)) => left.is(right),
+            (Self::Instance(left), Self::Instance(right)) => left.is(right),
             (Self::Nil, Self::Nil) => true,
             _ => false,
         }
@@ -73,6 +234,24 @@ impl fmt::Display for Value {
             Self::Bool(b) => b.fmt(f),
             Self::Number(n) => n.fmt(f),
             Self::String(s) => write!(f, "{s}"),
+            Self::List(elements) => write!(
+                f,
+                "[{}]",
+                elements
+                    .borrow()
+                    .iter()
+                    .map(ToString::to_string)
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+            Self::Map(map) => write!(
+                f,
+                "{{{}}}",
+                (map.entries().iter())
+                    .map(|(key, value)| format!("{key}: {value}"))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
             Self::NativeFunction(nf) => write!(f, "{nf:?}"),
             Self::Function(func) => write!(f, "{func:?}"),
             Self::Class(class) => write!(f, "{class:?}"),
@@ -1,14 +1,39 @@
-use std::io::Write;
+use std::{
+    collections::HashSet,
+    io::{BufRead, Write},
+    rc::Rc,
+    time::Instant,
+};
 
 use thiserror::Error;
 
-use self::{interpreter::Interpreter, parser::Parser, resolver::Resolver, scanner::Scanner};
+use self::{
+    ast_printer::AstPrinter, formatter::Formatter, interpreter::format_stack, resolver::Resolver,
+};
+
+pub use self::{
+    ast::{Expr, Literal, Stmt, Visitor},
+    interpreter::{
+        functions::{Arity, Callable, FunctionDeclaration, NativeFunction},
+        Clock, Error as RuntimeError, Interpreter, Value,
+    },
+    parser::{Error as ParserError, Parser},
+    scanner::{Error as ScannerError, Scanner},
+    symbol::Symbol,
+    token::Token,
+    token_type::TokenType,
+};
 
 mod ast;
+mod ast_printer;
+mod formatter;
 mod interpreter;
 mod parser;
 mod resolver;
 mod scanner;
+mod symbol;
+#[cfg(test)]
+mod test_support;
 mod token;
 mod token_type;
 
@@ -32,35 +57,362 @@ pub enum Error {
 
 type Result<T = (), E = Error> = std::result::Result<T, E>;
 
+/// The phase of execution in which an error occurred.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Phase {
+    Scanning,
+    Parsing,
+    Resolution,
+    Runtime,
+    Io,
+}
+
+/// A structured description of a single error, for embedders that need more
+/// than the formatted message.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub phase: Phase,
+    /// The name of the error variant, e.g. `UnterminatedString`.
+    pub kind: &'static str,
+    pub line: Option<usize>,
+    /// The character column on `line`, counting from 1, when the error points at a token.
+    pub column: Option<usize>,
+    pub message: String,
+    /// For a runtime error raised inside function calls, the name and call-site line of
+    /// each call, outermost first. Empty for errors raised outside any call.
+    pub stack: Vec<(String, usize)>,
+}
+
+impl Diagnostic {
+    fn new<E: std::fmt::Display>(
+        phase: Phase,
+        kind: &'static str,
+        error: &E,
+        line: Option<usize>,
+        column: Option<usize>,
+    ) -> Self {
+        Self {
+            phase,
+            kind,
+            line,
+            column,
+            message: error.to_string(),
+            stack: Vec::new(),
+        }
+    }
+}
+
+impl Error {
+    /// Whether the error only comes from the source ending early, so that more input
+    /// could still make it valid.
+    pub fn is_incomplete(&self) -> bool {
+        match self {
+            Self::Scanner(errors) => errors
+                .iter()
+                .all(|e| matches!(e, scanner::Error::UnterminatedString { .. })),
+            Self::Parser(errors) => errors
+                .iter()
+                .all(|e| matches!(e, parser::Error::UnexpectedEof { .. })),
+            _ => false,
+        }
+    }
+
+    /// Breaks the error down into one diagnostic per underlying error.
+    pub fn diagnostics(&self) -> Vec<Diagnostic> {
+        match self {
+            Self::Scanner(errors) => errors
+                .iter()
+                .map(|e| Diagnostic::new(Phase::Scanning, e.kind(), e, e.line(), e.column()))
+                .collect(),
+            Self::Parser(errors) => errors
+                .iter()
+                .map(|e| {
+                    let (line, column) = (Some(e.line()), Some(e.column()));
+                    Diagnostic::new(Phase::Parsing, e.kind(), e, line, column)
+                })
+                .collect(),
+            Self::Resolver(errors) => errors
+                .iter()
+                .map(|e| {
+                    let (line, column) = (Some(e.line()), Some(e.column()));
+                    Diagnostic::new(Phase::Resolution, e.kind(), e, line, column)
+                })
+                .collect(),
+            // A traceback describes the error that was raised, with the calls that led to it
+            Self::Runtime(RuntimeError::Traceback { error, stack }) => {
+                let (line, column) = (error.line(), error.column());
+                let diagnostic = Diagnostic::new(Phase::Runtime, error.kind(), error, line, column);
+                vec![Diagnostic {
+                    stack: stack.clone(),
+                    ..diagnostic
+                }]
+            }
+            Self::Runtime(e) => {
+                let diagnostic = Diagnostic::new(Phase::Runtime, e.kind(), e, e.line(), e.column());
+                vec![diagnostic]
+            }
+            Self::Io(e) => vec![Diagnostic::new(Phase::Io, "Io", e, None, None)],
+        }
+    }
+
+    /// Formats the error like `Display`, but follows each message that points at a token
+    /// with the offending source line and a caret under the token.
+    pub fn render(&self, source: &str) -> String {
+        let mut rendered = self
+            .diagnostics()
+            .into_iter()
+            .map(|diagnostic| {
+                let message = match (diagnostic.line, diagnostic.column) {
+                    (Some(line), Some(column)) => {
+                        format_error_with_source(source, line, column, &diagnostic.message)
+                    }
+                    _ => diagnostic.message,
+                };
+                message + &format_stack(&diagnostic.stack)
+            })
+            .collect::<Vec<_>>();
+
+        match self {
+            Self::Scanner(_) => rendered.push("Scanning failed, see errors above.".to_string()),
+            Self::Parser(_) => rendered.push("Parsing failed, see errors above.".to_string()),
+            Self::Resolver(_) => {
+                rendered.push("Variable resolution failed, see errors above.".to_string())
+            }
+            Self::Runtime(_) | Self::Io(_) => {}
+        }
+        rendered.join("\n")
+    }
+}
+
+/// Appends the source line to an error message, with a caret under the given column, e.g.
+///
+/// ```text
+/// [line 2] Undefined variable x.
+///   2 | print x;
+///     |       ^
+/// ```
+fn format_error_with_source(source: &str, line: usize, column: usize, message: &str) -> String {
+    let Some(text) = source.lines().nth(line.saturating_sub(1)) else {
+        return message.to_string();
+    };
+
+    // Keep tabs from the source line so the caret lines up however wide they are displayed
+    let padding = text
+        .chars()
+        .take(column.saturating_sub(1))
+        .map(|c| if c == '\t' { '\t' } else { ' ' })
+        .collect::<String>();
+
+    let gutter = " ".repeat(line.to_string().len());
+    format!("{message}\n{line} | {text}\n{gutter} | {padding}^")
+}
+
+/// A function in a program and the local variables it captures from enclosing scopes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FunctionCaptures {
+    pub name: String,
+    pub line: usize,
+    pub variables: Vec<String>,
+}
+
+/// The global names a program references, found without running it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AuditReport {
+    /// Referenced globals that are currently bound to native functions, e.g. `clock`.
+    pub natives: Vec<String>,
+    /// Every other referenced global, whether defined by the program, the embedder, or not at all.
+    pub globals: Vec<String>,
+}
+
 pub struct Loxide {
     interpreter: Interpreter,
+    warnings: bool,
+    timings: bool,
+    /// Constants declared at the top level by earlier runs, which later ones can't assign
+    global_consts: HashSet<Symbol>,
+}
+
+impl Default for Loxide {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Loxide {
     pub fn new() -> Self {
         Self {
             interpreter: Interpreter::new(),
+            warnings: false,
+            timings: false,
+            global_consts: HashSet::new(),
         }
     }
 
-    fn run(&mut self, source: Vec<u8>) -> Result {
+    /// Enables printing resolver warnings, such as an assignment used as a condition, to stderr.
+    pub fn set_warnings(&mut self, enabled: bool) {
+        self.warnings = enabled;
+    }
+
+    /// Enables printing how long each phase of running a script took to stderr.
+    pub fn set_timings(&mut self, enabled: bool) {
+        self.timings = enabled;
+    }
+
+    /// Redirects the output of `print` statements from stdout, e.g. into a shared buffer
+    /// so a host can capture what scripts print.
+    pub fn set_output(&mut self, output: Box<dyn Write>) {
+        self.interpreter.set_output(output);
+    }
+
+    /// Replaces stdin as the source of lines for `read_line`, e.g. with scripted input.
+    pub fn set_input(&mut self, input: Box<dyn BufRead>) {
+        self.interpreter.set_input(input);
+    }
+
+    /// Replaces the time source of `clock` and timers, e.g. with one backed by `Date.now()`
+    /// when running in a browser, where the system clock isn't available.
+    pub fn set_clock(&mut self, clock: Clock) {
+        self.interpreter.set_clock(clock);
+    }
+
+    /// Limits how deeply function calls can nest, see `Interpreter::set_max_depth`.
+    pub fn set_max_depth(&mut self, max_depth: usize) {
+        self.interpreter.set_max_depth(max_depth);
+    }
+
+    /// Limits how much native stack function calls can use, see `Interpreter::set_max_stack`.
+    pub fn set_max_stack(&mut self, max_stack: usize) {
+        self.interpreter.set_max_stack(max_stack);
+    }
+
+    /// Defines a global variable that scripts run afterwards can read.
+    pub fn set_global(&mut self, name: &str, value: Value) {
+        self.interpreter.define_global(name, value);
+    }
+
+    /// Defines a native function that scripts run afterwards can call, e.g.
+    /// `loxide.define_native("double", 1, |_, args| ...)`. The function can be a closure,
+    /// to share state with the host program. The arity can be a count, or any
+    /// `Arity` for optional or variadic arguments. Redefining a name replaces it.
+    pub fn define_native(
+        &mut self,
+        name: &str,
+        arity: impl Into<Arity>,
+        function: impl Fn(&mut Interpreter, Vec<Value>) -> Result<Value, RuntimeError> + 'static,
+    ) {
+        let native = NativeFunction::new(name, arity, function);
+        self.interpreter
+            .define_global(name, Value::NativeFunction(Rc::new(native)));
+    }
+
+    /// Scans a script into tokens without parsing or running it.
+    pub fn scan(&self, source: &[u8]) -> Result<Vec<Token>> {
+        Scanner::new(source.to_vec())
+            .scan_tokens()
+            .map_err(Error::Scanner)
+    }
+
+    /// Parses a script without running it, rendering each statement as an s-expression.
+    pub fn dump_ast(&self, source: &[u8]) -> Result<String> {
+        let statements = self.parse(source.to_vec())?;
+        Ok(AstPrinter.print(&statements))
+    }
+
+    /// Parses a script without running it, printing it back as canonically formatted source
+    /// with its comments.
+    pub fn format(&self, source: &[u8]) -> Result<String> {
+        let mut scanner = Scanner::new(source.to_vec());
+        let tokens = scanner.scan_tokens().map_err(Error::Scanner)?;
+
+        let statements = Parser::new(tokens)
+            .with_comments(scanner.take_comments())
+            .parse()
+            .map_err(Error::Parser)?;
+        Ok(Formatter::new().format(&statements))
+    }
+
+    /// Parses a source string without running it, serializing the statements as JSON for
+    /// external tools.
+    pub fn parse_to_json(&self, source: &str) -> Result<String> {
+        let statements = self.parse(source.as_bytes().to_vec())?;
+        // The AST is plain data with string keys only, so serializing it can't fail
+        Ok(serde_json::to_string(&statements).expect("Failed to serialize the AST"))
+    }
+
+    /// Lists the variables captured by each function in the source, ordered by declaration line.
+    pub fn captured_variables(&self, source: &str) -> Result<Vec<FunctionCaptures>> {
+        let statements = self.parse(source.as_bytes().to_vec())?;
+        let resolution = Resolver::new().run(&statements).map_err(Error::Resolver)?;
+
+        let mut functions = resolution
+            .captures
+            .into_iter()
+            .map(|(declaration, variables)| FunctionCaptures {
+                name: declaration.name.get_lexeme(),
+                line: declaration.name.get_line(),
+                variables: variables.into_iter().collect(),
+            })
+            .collect::<Vec<_>>();
+        functions.sort_by_key(|function| function.line);
+        Ok(functions)
+    }
+
+    /// Reports which globals, and in particular which natives, the source references without
+    /// running it, e.g. to decide whether an untrusted script needs a sandbox.
+    pub fn audit(&self, source: &str) -> Result<AuditReport> {
+        let statements = self.parse(source.as_bytes().to_vec())?;
+        let resolution = Resolver::new().run(&statements).map_err(Error::Resolver)?;
+
+        let (natives, globals) =
+            (resolution.globals.into_iter()).partition(|name| self.interpreter.is_native(name));
+        Ok(AuditReport { natives, globals })
+    }
+
+    fn parse(&self, source: Vec<u8>) -> Result<Vec<Stmt>> {
         let mut scanner = Scanner::new(source);
         let tokens = scanner.scan_tokens().map_err(Error::Scanner)?;
 
         let mut parser = Parser::new(tokens);
-        let statements = parser.parse().map_err(Error::Parser)?;
+        parser.parse().map_err(Error::Parser)
+    }
 
-        let locals = Resolver::new().run(&statements).map_err(Error::Resolver)?;
-        self.interpreter.update_locals(locals);
+    /// Runs a source string, returning the value of its final expression statement,
+    /// e.g. `3` for `var x = 1; x + 2;`. Sources that don't end in an expression give nil.
+    pub fn eval(&mut self, source: &str) -> Result<Value> {
+        self.run(source.as_bytes().to_vec())
+    }
 
-        self.interpreter
-            .interpret(&statements)
-            .map_err(Error::Runtime)
+    fn run(&mut self, source: Vec<u8>) -> Result<Value> {
+        let start = Instant::now();
+        let tokens = Scanner::new(source).scan_tokens().map_err(Error::Scanner)?;
+        let scanned = Instant::now();
+        let statements = Parser::new(tokens).parse().map_err(Error::Parser)?;
+        let parsed = Instant::now();
+
+        let resolution = Resolver::with_global_consts(self.global_consts.clone())
+            .run(&statements)
+            .map_err(Error::Resolver)?;
+        if self.warnings {
+            (resolution.warnings.iter()).for_each(|warning| eprintln!("{warning}"));
+        }
+        self.interpreter.update_locals(resolution.locals);
+        self.global_consts = resolution.global_consts;
+        let resolved = Instant::now();
+
+        let result = self.interpreter.interpret(&statements);
+        if self.timings {
+            eprintln!("scan:      {:?}", scanned - start);
+            eprintln!("parse:     {:?}", parsed - scanned);
+            eprintln!("resolve:   {:?}", resolved - parsed);
+            eprintln!("interpret: {:?}", resolved.elapsed());
+        }
+        result.map_err(Error::Runtime)
     }
 
-    pub fn run_file(&mut self, path: &str) -> Result {
-        let source = std::fs::read(path)?;
-        self.run(source)
+    /// Runs a whole script. Unlike `eval`, the source doesn't need to be valid UTF-8, invalid
+    /// characters are reported as scanner errors.
+    pub fn run_script(&mut self, source: &[u8]) -> Result {
+        self.run(source.to_vec()).map(|_| ())
     }
 
     pub fn run_repl(&mut self) -> Result {
@@ -70,26 +422,34 @@ impl Loxide {
         // Create a handle to stdout
         let mut stdout = std::io::stdout();
 
+        // Lines of an incomplete statement waiting for more input
+        let mut source = String::new();
+
         loop {
-            // Print the prompt
-            print!("> ");
+            // Print the prompt, using a continuation prompt for an incomplete statement
+            print!("{}", if source.is_empty() { "> " } else { "... " });
             stdout.flush()?;
 
-            // Read a line from stdin
+            // Read a line from stdin, where reading zero bytes means EOF (e.g. Ctrl-D).
+            // A blank line still contains its newline, so it just prompts again.
             let mut buffer = String::new();
-            stdin.read_line(&mut buffer)?;
-
-            // If the buffer is empty, break
-            if buffer.is_empty() {
+            if stdin.read_line(&mut buffer)? == 0 {
                 println!("Exiting...");
                 break;
             }
 
-            // Run the line
-            match self.run(buffer.into_bytes()) {
+            // Run the accumulated input, waiting for more lines if it's incomplete or the
+            // line ends with a backslash to continue it
+            source.push_str(&buffer);
+            if buffer.trim_end_matches(['\r', '\n']).ends_with('\\') {
+                continue;
+            }
+            match self.run(source.clone().into_bytes()) {
+                Err(e) if e.is_incomplete() => continue,
                 Ok(_) => {}
-                Err(e) => println!("{e}"),
+                Err(e) => println!("{}", e.render(&source)),
             }
+            source.clear();
 
             // Flush stdout
             stdout.flush()?;
@@ -98,3 +458,281 @@ impl Loxide {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::Cell, rc::Rc};
+
+    use super::{
+        test_support::{error, output, session},
+        Arity, Diagnostic, Error, FunctionCaptures, Loxide, Phase, RuntimeError, Value,
+    };
+
+    #[test]
+    fn repl_lines_with_identical_tokens_resolve_independently() {
+        let (mut loxide, output) = session();
+        loxide.eval("{ var a = 1; print a; }").unwrap();
+        let error = loxide.eval("{ var b = 2; print a; }").unwrap_err();
+
+        assert_eq!(output.text(), "1\n");
+        assert!(matches!(
+            error,
+            Error::Runtime(RuntimeError::UndefinedVariable { ref name, .. }) if name == "a"
+        ));
+    }
+
+    #[test]
+    fn diagnostics_describe_each_error() {
+        assert_eq!(
+            error("print \"open;").diagnostics(),
+            [Diagnostic {
+                phase: Phase::Scanning,
+                kind: "UnterminatedString",
+                line: Some(1),
+                column: Some(7),
+                message: String::from("[line 1] Unterminated string"),
+                stack: Vec::new(),
+            }]
+        );
+
+        let diagnostics = error("{\n  return 1;\n}\nbreak;").diagnostics();
+        assert_eq!(
+            (diagnostics.iter())
+                .map(|d| (d.phase, d.kind, d.line, d.column))
+                .collect::<Vec<_>>(),
+            [
+                (Phase::Resolution, "ReturnOutsideFunction", Some(2), Some(3)),
+                (Phase::Resolution, "BreakOutsideLoop", Some(4), Some(1)),
+            ]
+        );
+
+        let [diagnostic] = &error("var x = 1;\nprint x + nil;").diagnostics()[..] else {
+            panic!("Expected a single diagnostic");
+        };
+        assert_eq!(
+            (
+                diagnostic.phase,
+                diagnostic.kind,
+                diagnostic.line,
+                diagnostic.column
+            ),
+            (Phase::Runtime, "InvalidOperand", Some(2), Some(9))
+        );
+    }
+
+    #[test]
+    fn diagnostics_unwrap_tracebacks() {
+        let source = "fn f() {\n  return nil + 1;\n}\nfn g() { f(); }\ng();";
+        let error = error(source);
+        assert_eq!(
+            error.diagnostics(),
+            [Diagnostic {
+                phase: Phase::Runtime,
+                kind: "InvalidOperand",
+                line: Some(2),
+                column: Some(14),
+                message: String::from("[line 2] Operator `+` expected one of: [Number, String], found nil of type Nil."),
+                stack: vec![(String::from("g"), 5), (String::from("f"), 4)],
+            }]
+        );
+        assert_eq!(
+            error.render(source),
+            "[line 2] Operator `+` expected one of: [Number, String], found nil of type Nil.\n\
+             2 |   return nil + 1;\n  \
+             |              ^\n    \
+             in f (line 4)\n    \
+             in g (line 5)"
+        );
+    }
+
+    #[test]
+    fn constants_stay_constant_across_repl_lines() {
+        let (mut loxide, output) = session();
+        loxide.eval("const PI = 3;").unwrap();
+        let error = loxide.eval("PI = 4;").unwrap_err();
+        loxide.eval("print PI;").unwrap();
+
+        assert_eq!(output.text(), "3\n");
+        assert!(matches!(error, Error::Resolver(ref errors) if errors.len() == 1));
+    }
+
+    #[test]
+    fn redeclaring_a_constant_with_var_makes_it_assignable() {
+        assert_eq!(output("const X = 1; var X = 2; X = 3; print X;"), "3\n");
+        assert!(matches!(
+            error("var X = 1; const X = 2; X = 3;"),
+            Error::Resolver(_)
+        ));
+    }
+
+    #[test]
+    fn shadowed_variables_resolve_to_the_innermost_declaration() {
+        let source = "
+            {
+                var a = \"outer\";
+                {
+                    var a = \"inner\";
+                    fn f() { print a; }
+                    f();
+                    print a;
+                }
+                print a;
+            }";
+        assert_eq!(output(source), "inner\ninner\nouter\n");
+    }
+
+    #[test]
+    fn closures_capture_only_the_outer_variables_they_use() {
+        let source = "
+            fn outer() {
+                var used = 1;
+                var ignored = 2;
+                fn inner(x) { return x + used; }
+                return inner;
+            }";
+        let (loxide, _) = session();
+        assert_eq!(
+            loxide.captured_variables(source).unwrap(),
+            [
+                FunctionCaptures {
+                    name: String::from("outer"),
+                    line: 2,
+                    variables: Vec::new(),
+                },
+                FunctionCaptures {
+                    name: String::from("inner"),
+                    line: 5,
+                    variables: vec![String::from("used")],
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn natives_defined_by_the_embedder_can_take_any_arity() {
+        let (mut loxide, output) = session();
+        loxide.define_native("count", Arity::AtLeast(0), |_, arguments| {
+            Ok(Value::from(arguments.len() as f64))
+        });
+        loxide.eval("print count(); print count(1, 2, 3);").unwrap();
+        assert_eq!(output.text(), "0\n3\n");
+    }
+
+    #[test]
+    fn natives_can_be_closures_over_host_state() {
+        let (mut loxide, output) = session();
+        let calls = Rc::new(Cell::new(0));
+        let counter = Rc::clone(&calls);
+        loxide.define_native("tick", 0, move |_, _| {
+            counter.set(counter.get() + 1);
+            Ok(Value::from(counter.get() as f64))
+        });
+        loxide.eval("tick(); print tick();").unwrap();
+
+        assert_eq!(output.text(), "2\n");
+        assert_eq!(calls.get(), 2);
+    }
+
+    #[test]
+    fn audits_report_referenced_natives_and_globals() {
+        let (mut loxide, _) = session();
+        loxide.define_native("readFile", 1, |_, _| Ok(Value::Nil));
+
+        let report = loxide
+            .audit("var path = config; fn main() { var local = readFile(path); print clock(); }")
+            .unwrap();
+        assert_eq!(report.natives, ["clock", "readFile"]);
+        assert_eq!(report.globals, ["config", "path"]);
+
+        let report = loxide.audit("var x = 1 + 2; print x * 3;").unwrap();
+        assert!(report.natives.is_empty());
+    }
+
+    #[test]
+    fn scripts_continue_lines_ending_in_a_backslash() {
+        assert_eq!(output("print 1 \\\n  + 2;\nprint 3;"), "3\n3\n");
+        assert_eq!(
+            error("print 1;\nprint 2 \\\n  +;").diagnostics()[0].line,
+            Some(3)
+        );
+    }
+
+    #[test]
+    fn globals_set_from_rust_can_be_read_and_shadowed() {
+        let (mut loxide, output) = session();
+        loxide.set_global("version", Value::String(String::from("1.2")));
+        loxide
+            .eval("print version; { var version = 2; print version; } print version;")
+            .unwrap();
+        assert_eq!(output.text(), "1.2\n2\n1.2\n");
+    }
+
+    #[test]
+    fn elapsed_measures_seconds_since_the_timer_started() {
+        let (mut loxide, output) = session();
+        loxide.eval("var t = timerStart();").unwrap();
+        std::thread::sleep(std::time::Duration::from_millis(20));
+        loxide
+            .eval("var e = elapsed(t); print e >= 0.02 and e < 10;")
+            .unwrap();
+        assert_eq!(output.text(), "true\n");
+    }
+
+    #[test]
+    fn timers_read_the_injected_clock() {
+        let (mut loxide, output) = session();
+        loxide.set_clock(|| Ok(100.0));
+        loxide
+            .eval("var t = timerStart(); print t; print elapsed(t); print clock();")
+            .unwrap();
+        assert_eq!(output.text(), "100\n0\n100\n");
+    }
+
+    #[test]
+    fn formatting_keeps_blocks_that_are_not_for_loops() {
+        let format = |source: &str| session().0.format(source.as_bytes());
+
+        assert_eq!(
+            format("{ var k = 0; while (k < 1) k = k + 1; }").unwrap(),
+            "{\n    var k = 0;\n    while (k < 1) k = k + 1;\n}\n"
+        );
+        assert_eq!(
+            format("for (var i = 0; i < 2; i = i + 1) print i;").unwrap(),
+            "for (var i = 0; i < 2; i = i + 1) print i;\n"
+        );
+    }
+
+    #[test]
+    fn the_ast_serializes_to_json_with_positions() {
+        let json = Loxide::new()
+            .parse_to_json("var x = 1 + 2;\nfn f(a) { return a; }")
+            .unwrap();
+        let statements: serde_json::Value = serde_json::from_str(&json).unwrap();
+
+        let initializer = &statements[0]["Var"]["initializer"]["Binary"];
+        assert_eq!(
+            initializer["left"],
+            serde_json::json!({ "Literal": { "Number": 1.0 } })
+        );
+        assert_eq!(initializer["operator"]["token_type"], "Plus");
+        assert_eq!(initializer["operator"]["column"], 11);
+
+        let function = &statements[1]["Function"];
+        assert_eq!(function["name"]["lexeme"], "f");
+        assert_eq!(function["name"]["line"], 2);
+        assert_eq!(function["params"][0]["lexeme"], "a");
+        assert_eq!(
+            function["body"][0]["Return"]["value"]["Variable"]["lexeme"],
+            "a"
+        );
+
+        assert!(matches!(
+            Loxide::new().parse_to_json("var x = ;"),
+            Err(Error::Parser(_))
+        ));
+        assert!(matches!(
+            Loxide::new().parse_to_json("var x = \"open;"),
+            Err(Error::Scanner(_))
+        ));
+    }
+}
@@ -1,57 +1,209 @@
+use std::{collections::VecDeque, fmt, rc::Rc};
+
 use thiserror::Error;
 
 use super::{
-    ast::{Expr, Literal, Stmt},
-    interpreter::functions::FunctionDeclaration,
+    ast::{Expr, Literal, MatchArm, Stmt},
+    interpreter::{functions::FunctionDeclaration, TYPE_NAMES},
+    scanner::{Comment, Error as ScannerError, Scanner},
     token::Token,
-    token_type::TokenType,
+    token_type::{TokenType, KEYWORDS},
 };
 
 #[derive(Debug, Error)]
 pub enum Error {
     #[error("[line {line}] {msg}")]
-    Syntax { msg: String, line: usize },
+    Syntax {
+        msg: String,
+        line: usize,
+        column: usize,
+    },
 
     #[error("[line {line}] Too many arguments in function call.")]
-    TooManyArguments { line: usize },
+    TooManyArguments { line: usize, column: usize },
+
+    /// A syntax error caused by the source ending early, so more input could complete it.
+    #[error("[line {line}] {msg}")]
+    UnexpectedEof {
+        msg: String,
+        line: usize,
+        column: usize,
+    },
 }
 
 type Result<T, E = Error> = std::result::Result<T, E>;
 
+impl Error {
+    /// The name of the variant, e.g. `UnexpectedEof`.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::Syntax { .. } => "Syntax",
+            Self::TooManyArguments { .. } => "TooManyArguments",
+            Self::UnexpectedEof { .. } => "UnexpectedEof",
+        }
+    }
+
+    pub fn line(&self) -> usize {
+        match self {
+            Self::Syntax { line, .. }
+            | Self::TooManyArguments { line, .. }
+            | Self::UnexpectedEof { line, .. } => *line,
+        }
+    }
+
+    pub fn column(&self) -> usize {
+        match self {
+            Self::Syntax { column, .. }
+            | Self::TooManyArguments { column, .. }
+            | Self::UnexpectedEof { column, .. } => *column,
+        }
+    }
+}
+
+/// What a named function is declared as, which decides whether it can be a getter.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FunctionKind {
+    Function,
+    Method,
+    StaticMethod,
+}
+
+impl fmt::Display for FunctionKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Function => write!(f, "function"),
+            Self::Method => write!(f, "method"),
+            Self::StaticMethod => write!(f, "static method"),
+        }
+    }
+}
+
 pub struct Parser {
-    tokens: Vec<Token>,
+    /// Shared so the AST can hold on to tokens without copying them
+    tokens: Vec<Rc<Token>>,
     current: usize,
+    /// Where tokens come from as they're needed, when streaming instead of given up front
+    scanner: Option<Scanner>,
+    scanner_errors: Vec<ScannerError>,
+    /// The comments of the source that haven't been parsed into statements yet, in order
+    comments: VecDeque<Comment>,
 }
 
 impl Parser {
     pub fn new(tokens: Vec<Token>) -> Self {
-        Self { tokens, current: 0 }
+        Self {
+            tokens: tokens.into_iter().map(Rc::new).collect(),
+            current: 0,
+            scanner: None,
+            scanner_errors: Vec::new(),
+            comments: VecDeque::new(),
+        }
+    }
+
+    /// Keeps the source's comments, parsing them into `Stmt::Comment`s next to the
+    /// statements they're on or before, and into the declarations of the methods they're
+    /// before, so the formatter can write them back. A comment inside a statement, e.g.
+    /// between the elements of a list, is moved before the statement.
+    pub fn with_comments(mut self, comments: Vec<Comment>) -> Self {
+        self.comments = comments.into();
+        self
+    }
+
+    /// Creates a parser that pulls tokens from the scanner only as it needs them, instead
+    /// of scanning the whole source up front. Tokens the scanner fails on are skipped and
+    /// collected for `take_scanner_errors`, which should be checked after parsing, since
+    /// a skipped token usually causes a syntax error as well.
+    pub fn streaming(scanner: Scanner) -> Self {
+        let mut parser = Self {
+            tokens: Vec::new(),
+            current: 0,
+            scanner: Some(scanner),
+            scanner_errors: Vec::new(),
+            comments: VecDeque::new(),
+        };
+        parser.fill();
+        parser
+    }
+
+    /// Returns the errors the scanner has reported to a streaming parser so far.
+    pub fn take_scanner_errors(&mut self) -> Vec<ScannerError> {
+        std::mem::take(&mut self.scanner_errors)
     }
 
     pub fn parse(&mut self) -> Result<Vec<Stmt>, Vec<Error>> {
+        let (statements, errors) = self.parse_recovering();
+        if errors.is_empty() {
+            Ok(statements)
+        } else {
+            Err(errors)
+        }
+    }
+
+    /// Parses as much as possible, returning the statements that parsed alongside the errors.
+    /// A declaration containing an error is skipped entirely, so tools can still work with
+    /// the rest of a file that has syntax errors.
+    pub fn parse_recovering(&mut self) -> (Vec<Stmt>, Vec<Error>) {
         let mut statements = Vec::new();
         let mut errors = Vec::new();
 
         while !self.is_at_end() {
-            match self.declaration() {
-                Ok(stmt) => statements.push(stmt),
-                Err(err) => errors.push(err),
+            if let Err(err) = self.commented_declaration(&mut statements) {
+                errors.push(err);
             }
         }
+        self.comments_before(usize::MAX, &mut statements);
 
-        if errors.is_empty() {
-            Ok(statements)
-        } else {
-            Err(errors)
+        (statements, errors)
+    }
+
+    /// Parses a declaration into `statements`, along with its comments: those on the lines
+    /// before it, those inside it that no nested statement took, which are moved before it,
+    /// and one ending its last line.
+    fn commented_declaration(&mut self, statements: &mut Vec<Stmt>) -> Result<()> {
+        self.comments_before(self.peek().get_line(), statements);
+        let stmt = self.declaration()?;
+
+        let end = self.previous().get_line();
+        self.comments_before(end, statements);
+        statements.push(stmt);
+
+        // A comment on the last line comes after the declaration, but it ends the line of the
+        // next declaration instead if that starts on the same line
+        if self.is_at_end() || self.peek().get_line() > end {
+            for comment in self.take_comments_before(end + 1) {
+                statements.push(Stmt::Comment {
+                    text: comment.text,
+                    trailing: true,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Moves the comments on lines before `line` into `statements`, each on its own line.
+    fn comments_before(&mut self, line: usize, statements: &mut Vec<Stmt>) {
+        for comment in self.take_comments_before(line) {
+            statements.push(Stmt::Comment {
+                text: comment.text,
+                trailing: false,
+            });
         }
     }
 
+    fn take_comments_before(&mut self, line: usize) -> Vec<Comment> {
+        let count = (self.comments.iter())
+            .take_while(|comment| comment.line < line)
+            .count();
+        self.comments.drain(..count).collect()
+    }
+
     fn declaration(&mut self) -> Result<Stmt> {
         let previous = self.advance(); // consume and return the current token
         let result = match previous.get_token_type() {
             TokenType::Class => self.class_declaration(),
             TokenType::Fn => self.function_statement(),
             TokenType::Var => self.var_declaration(),
+            TokenType::Const => self.const_declaration(),
             _ => {
                 self.restore(); // restore the previous token so we can parse it as a statement
                 self.statement()
@@ -79,8 +231,17 @@ impl Parser {
         self.consume(&TokenType::LeftBrace, "Expect '{' before class body.")?;
 
         let mut methods = Vec::new();
+        let mut static_methods = Vec::new();
         while !self.check(&TokenType::RightBrace) && !self.is_at_end() {
-            methods.push(self.function("method")?);
+            let comments = self.take_comments_before(self.peek().get_line());
+            let (kind, methods) = if self.match_token(&[TokenType::Class]) {
+                (FunctionKind::StaticMethod, &mut static_methods)
+            } else {
+                (FunctionKind::Method, &mut methods)
+            };
+            let mut method = self.function(kind)?;
+            method.comments = comments.into_iter().map(|comment| comment.text).collect();
+            methods.push(method);
         }
 
         self.consume(&TokenType::RightBrace, "Expect '}' after class body.")?;
@@ -88,32 +249,35 @@ impl Parser {
             name,
             superclass,
             methods,
+            static_methods,
         })
     }
 
     fn function_statement(&mut self) -> Result<Stmt> {
         if let TokenType::Identifier(_) = self.peek().get_token_type() {
             // If the next token is an identifier, it's a named function declaration
-            self.function("function").map(Stmt::Function)
+            self.function(FunctionKind::Function).map(Stmt::Function)
         } else {
-            // Otherwise, it's an anonymous function declaration
-            let lambda = self.lambda()?;
-            self.consume(
-                &TokenType::Semicolon,
-                "Expect ';' after anonymous function expression statement.",
-            )?;
-            Ok(Stmt::Expression(lambda))
+            // Otherwise, it's an anonymous function expression, which may be invoked immediately
+            self.restore(); // restore the `fn` so the lambda is parsed as an expression
+            self.expression_statement()
         }
     }
 
-    fn function(&mut self, kind: &str) -> Result<FunctionDeclaration> {
+    fn function(&mut self, kind: FunctionKind) -> Result<FunctionDeclaration> {
         let name = self.consume_identifier(&format!("Expect {kind} name."))?;
-        self.consume(
-            &TokenType::LeftParen,
-            &format!("Expect '(' after {kind} name."),
-        )?;
 
-        let params = self.parameters()?;
+        // A method without a parameter list is a getter, e.g. `area { ... }`
+        let is_getter = kind == FunctionKind::Method && self.check(&TokenType::LeftBrace);
+        let params = if is_getter {
+            Vec::new()
+        } else {
+            self.consume(
+                &TokenType::LeftParen,
+                &format!("Expect '(' after {kind} name."),
+            )?;
+            self.parameters()?
+        };
 
         self.consume(
             &TokenType::LeftBrace,
@@ -121,7 +285,13 @@ impl Parser {
         )?;
         let body = self.block()?;
 
-        Ok(FunctionDeclaration { name, params, body })
+        Ok(FunctionDeclaration {
+            name,
+            params,
+            body,
+            is_getter,
+            comments: Vec::new(),
+        })
     }
 
     fn var_declaration(&mut self) -> Result<Stmt> {
@@ -140,7 +310,23 @@ impl Parser {
         Ok(Stmt::Var { name, initializer })
     }
 
+    fn const_declaration(&mut self) -> Result<Stmt> {
+        let name = self.consume_identifier("Expect constant name.")?;
+        self.consume(&TokenType::Equal, "Expect '=' after constant name.")?;
+        let initializer = self.expression()?;
+
+        self.consume(
+            &TokenType::Semicolon,
+            "Expect ';' after constant declaration.",
+        )?;
+        Ok(Stmt::Const { name, initializer })
+    }
+
     fn statement(&mut self) -> Result<Stmt> {
+        if self.is_at_end() {
+            return Err(self.error("Expect expression."));
+        }
+
         let previous = self.advance(); // consume and return the current token
         match previous.get_token_type() {
             TokenType::Print => self.print_statement(),
@@ -148,8 +334,17 @@ impl Parser {
             TokenType::If => self.if_statement(),
             TokenType::While => self.while_statement(),
             TokenType::For => self.for_statement(),
+            TokenType::Switch => self.switch_statement(),
+            TokenType::Match => self.match_statement(),
             TokenType::Break => self.break_statement(),
+            TokenType::Continue => self.continue_statement(),
             TokenType::Return => self.return_statement(),
+            TokenType::Loop => {
+                // A loop in statement position doesn't need a trailing semicolon
+                let expr = self.loop_expression()?;
+                self.match_token(&[TokenType::Semicolon]);
+                Ok(Stmt::Expression(expr))
+            }
             _ => {
                 self.restore(); // restore the previous token so we can parse it as an expression
                 self.expression_statement()
@@ -170,13 +365,33 @@ impl Parser {
     }
 
     fn break_statement(&mut self) -> Result<Stmt> {
+        let keyword = self.previous();
+        // No value if the next token is a semicolon
+        let value = if self.check(&TokenType::Semicolon) {
+            None
+        } else {
+            Some(self.expression()?)
+        };
         self.consume(&TokenType::Semicolon, "Expect ';' after 'break'.")?;
-        Ok(Stmt::Break)
+        Ok(Stmt::Break { keyword, value })
+    }
+
+    fn continue_statement(&mut self) -> Result<Stmt> {
+        let keyword = self.previous();
+        self.consume(&TokenType::Semicolon, "Expect ';' after 'continue'.")?;
+        Ok(Stmt::Continue { keyword })
     }
 
     fn for_statement(&mut self) -> Result<Stmt> {
         self.consume(&TokenType::LeftParen, "Expect '(' after 'for'.")?;
 
+        // A loop variable followed by `in` makes this a `for-in` loop
+        if matches!(self.peek().get_token_type(), TokenType::Identifier(_))
+            && self.check_next(&TokenType::In)
+        {
+            return self.for_in_statement();
+        }
+
         // Parse initializer
         let initializer = if self.match_token(&[TokenType::Semicolon]) {
             // If the token is a semicolon, the initializer has been omitted
@@ -213,15 +428,13 @@ impl Parser {
         // for (initializer; condition; increment) body;
         // initializer; while (condition) { body; increment; }
 
-        // If there is an increment, add it to a block after the body
-        if let Some(increment) = increment {
-            body = Stmt::Block(vec![body, Stmt::Expression(increment)]);
-        }
-
-        // Wrap the body in a while loop with the condition
+        // Wrap the body in a while loop with the condition, keeping the increment
+        // separate so that it still runs when the body is exited with `continue`
         body = Stmt::While {
             condition,
             body: Box::new(body),
+            increment,
+            fresh_bindings: matches!(initializer, Some(Stmt::Var { .. })),
         };
 
         // If there is an initializer, add it before the while loop
@@ -232,6 +445,130 @@ impl Parser {
         Ok(body)
     }
 
+    fn for_in_statement(&mut self) -> Result<Stmt> {
+        let name = self.consume_identifier("Expect loop variable name.")?;
+        self.consume(&TokenType::In, "Expect 'in' after loop variable.")?;
+        let iterable = self.expression()?;
+        self.consume(&TokenType::RightParen, "Expect ')' after 'for' clauses.")?;
+        let body = self.statement()?;
+
+        Ok(Stmt::ForIn {
+            name,
+            iterable,
+            body: Box::new(body),
+        })
+    }
+
+    fn switch_statement(&mut self) -> Result<Stmt> {
+        let keyword = self.previous();
+        self.consume(&TokenType::LeftParen, "Expect '(' after 'switch'.")?;
+        let subject = self.expression()?;
+        self.consume(&TokenType::RightParen, "Expect ')' after 'switch' subject.")?;
+        self.consume(&TokenType::LeftBrace, "Expect '{' before 'switch' body.")?;
+
+        let mut cases = Vec::new();
+        let mut default = None;
+        while !self.check(&TokenType::RightBrace) && !self.is_at_end() {
+            if self.match_token(&[TokenType::Case]) {
+                let value = self.expression()?;
+                self.consume(&TokenType::Colon, "Expect ':' after case value.")?;
+                cases.push((value, self.case_body()?));
+            } else if self.match_token(&[TokenType::Default]) {
+                if default.is_some() {
+                    return Err(Error::Syntax {
+                        msg: "Switch statement can only have one 'default' case.".to_string(),
+                        line: self.previous().get_line(),
+                        column: self.previous().get_column(),
+                    });
+                }
+                self.consume(&TokenType::Colon, "Expect ':' after 'default'.")?;
+                default = Some(self.case_body()?);
+            } else {
+                return Err(self.error("Expect 'case' or 'default' in 'switch' body."));
+            }
+        }
+
+        self.consume(&TokenType::RightBrace, "Expect '}' after 'switch' body.")?;
+        Ok(Stmt::Switch {
+            keyword,
+            subject,
+            cases,
+            default,
+        })
+    }
+
+    fn match_statement(&mut self) -> Result<Stmt> {
+        let subject = self.expression()?;
+        self.consume(&TokenType::LeftBrace, "Expect '{' after 'match' subject.")?;
+
+        let mut arms = Vec::new();
+        let mut else_branch = None;
+        while !self.check(&TokenType::RightBrace) && !self.is_at_end() {
+            if self.match_token(&[TokenType::Else]) {
+                if else_branch.is_some() {
+                    return Err(Error::Syntax {
+                        msg: "Match statement can only have one 'else' arm.".to_string(),
+                        line: self.previous().get_line(),
+                        column: self.previous().get_column(),
+                    });
+                }
+                self.consume(&TokenType::FatArrow, "Expect '=>' after 'else'.")?;
+                else_branch = Some(Box::new(self.statement()?));
+                continue;
+            }
+
+            let type_name =
+                self.consume_identifier("Expect type name or 'else' in 'match' body.")?;
+            if !TYPE_NAMES.contains(&type_name.get_lexeme().as_str()) {
+                return Err(Error::Syntax {
+                    msg: format!(
+                        "Unknown type '{}' in 'match' arm, expected one of: {}.",
+                        type_name.get_lexeme(),
+                        TYPE_NAMES.join(", ")
+                    ),
+                    line: type_name.get_line(),
+                    column: type_name.get_column(),
+                });
+            }
+
+            // The type name may be followed by a name to bind the subject to
+            let binding = match self.peek().get_token_type() {
+                TokenType::Identifier(_) => Some(self.advance()),
+                _ => None,
+            };
+            self.consume(&TokenType::FatArrow, "Expect '=>' after 'match' pattern.")?;
+            arms.push(MatchArm {
+                type_name,
+                binding,
+                body: self.statement()?,
+            });
+        }
+
+        self.consume(&TokenType::RightBrace, "Expect '}' after 'match' body.")?;
+        Ok(Stmt::Match {
+            subject,
+            arms,
+            else_branch,
+        })
+    }
+
+    fn case_body(&mut self) -> Result<Vec<Stmt>> {
+        let mut statements = Vec::new();
+
+        // A case body runs until the next case label or the end of the switch
+        while !self.is_at_end()
+            && !matches!(
+                self.peek().get_token_type(),
+                TokenType::Case | TokenType::Default | TokenType::RightBrace
+            )
+        {
+            self.commented_declaration(&mut statements)?;
+        }
+        self.comments_before(self.peek().get_line(), &mut statements);
+
+        Ok(statements)
+    }
+
     fn while_statement(&mut self) -> Result<Stmt> {
         self.consume(&TokenType::LeftParen, "Expect '(' after 'while'.")?;
         let condition = self.expression()?;
@@ -244,6 +581,8 @@ impl Parser {
         Ok(Stmt::While {
             condition,
             body: Box::new(body),
+            increment: None,
+            fresh_bindings: false,
         })
     }
 
@@ -270,17 +609,19 @@ impl Parser {
         let mut statements = Vec::new();
 
         while !self.is_at_end() && !self.check(&TokenType::RightBrace) {
-            statements.push(self.declaration()?);
+            self.commented_declaration(&mut statements)?;
         }
+        self.comments_before(self.peek().get_line(), &mut statements);
 
         self.consume(&TokenType::RightBrace, "Expect '}}' after block.")?;
         Ok(statements)
     }
 
     fn print_statement(&mut self) -> Result<Stmt> {
-        let expr = self.expression()?;
+        let keyword = self.previous();
+        let value = self.expression()?;
         self.consume(&TokenType::Semicolon, "Expect ';' after expression.")?;
-        Ok(Stmt::Print(expr))
+        Ok(Stmt::Print { keyword, value })
     }
 
     fn expression_statement(&mut self) -> Result<Stmt> {
@@ -290,20 +631,18 @@ impl Parser {
     }
 
     fn expression(&mut self) -> Result<Expr> {
-        if self.match_token(&[TokenType::Fn]) {
-            self.lambda()
-        } else {
-            self.assignment()
-        }
+        self.assignment()
     }
 
     fn lambda(&mut self) -> Result<Expr> {
         // Create a synthetic token for the anonymous function
-        let name = Token::new(
+        let name = Rc::new(Token::new(
             TokenType::Identifier(String::from("<anonymous>")),
             String::from("<anonymous>"),
-            self.previous().get_line(), // use the line of the `fn` keyword
-        );
+            // use the position of the `fn` keyword
+            self.previous().get_line(),
+            self.previous().get_column(),
+        ));
 
         self.consume(&TokenType::LeftParen, "Expect '(' after anonymous `fn`.")?;
         let params = self.parameters()?;
@@ -314,14 +653,27 @@ impl Parser {
         )?;
         let body = self.block()?;
 
-        Ok(Expr::Lambda(FunctionDeclaration { name, params, body }))
+        Ok(Expr::Lambda(FunctionDeclaration {
+            name,
+            params,
+            body,
+            is_getter: false,
+            comments: Vec::new(),
+        }))
+    }
+
+    fn loop_expression(&mut self) -> Result<Expr> {
+        self.consume(&TokenType::LeftBrace, "Expect '{' after 'loop'.")?;
+        Ok(Expr::Loop(self.block()?))
     }
 
     fn assignment(&mut self) -> Result<Expr> {
-        let expr = self.or()?;
+        let expr = self.ternary()?;
 
         if self.match_token(&[TokenType::Equal]) {
             let equals = self.previous();
+            // Recurse so that chained assignment is right-associative: `a.x = b[0] = c`
+            // parses as `a.x = (b[0] = c)`, and each target's object is evaluated only once
             let value = self.assignment()?;
 
             match expr {
@@ -334,9 +686,20 @@ impl Parser {
                     name,
                     value: Box::new(value),
                 }),
+                Expr::Index {
+                    object,
+                    index,
+                    bracket,
+                } => Ok(Expr::SetIndex {
+                    object,
+                    index,
+                    bracket,
+                    value: Box::new(value),
+                }),
                 _ => Err(Error::Syntax {
                     msg: "Invalid assignment target.".to_string(),
                     line: equals.get_line(),
+                    column: equals.get_column(),
                 }),
             }
         } else {
@@ -344,6 +707,28 @@ impl Parser {
         }
     }
 
+    fn ternary(&mut self) -> Result<Expr> {
+        let expr = self.or()?;
+
+        if self.match_token(&[TokenType::Question]) {
+            let then_branch = self.expression()?;
+            self.consume(
+                &TokenType::Colon,
+                "Expect ':' after then branch of conditional expression.",
+            )?;
+            // Recurse for the else branch so that nested conditionals are right-associative
+            let else_branch = self.ternary()?;
+
+            Ok(Expr::Ternary {
+                condition: Box::new(expr),
+                then_branch: Box::new(then_branch),
+                else_branch: Box::new(else_branch),
+            })
+        } else {
+            Ok(expr)
+        }
+    }
+
     fn or(&mut self) -> Result<Expr> {
         let mut expr = self.and()?;
 
@@ -377,11 +762,11 @@ impl Parser {
     }
 
     fn equality(&mut self) -> Result<Expr> {
-        let mut expr = self.comparison()?;
+        let mut expr = self.bitwise_or()?;
 
         while self.match_token(&[TokenType::BangEqual, TokenType::EqualEqual]) {
             let operator = self.previous();
-            let right = self.comparison()?;
+            let right = self.bitwise_or()?;
             expr = Expr::Binary {
                 left: Box::new(expr),
                 operator,
@@ -392,8 +777,60 @@ impl Parser {
         Ok(expr)
     }
 
+    // The bitwise operators bind tighter than equality, so `x & 1 == 0` tests the masked
+    // value rather than masking a bool. From loosest to tightest, the levels below
+    // equality are:
+    //   `|`, `^`, `&`, comparison, `<<` `>>`, `+` `-`, `*` `/`, `**`, unary (including `~`)
+    fn bitwise_or(&mut self) -> Result<Expr> {
+        let mut expr = self.bitwise_xor()?;
+
+        while self.match_token(&[TokenType::Pipe]) {
+            let operator = self.previous();
+            let right = self.bitwise_xor()?;
+            expr = Expr::Binary {
+                left: Box::new(expr),
+                operator,
+                right: Box::new(right),
+            }
+        }
+
+        Ok(expr)
+    }
+
+    fn bitwise_xor(&mut self) -> Result<Expr> {
+        let mut expr = self.bitwise_and()?;
+
+        while self.match_token(&[TokenType::Caret]) {
+            let operator = self.previous();
+            let right = self.bitwise_and()?;
+            expr = Expr::Binary {
+                left: Box::new(expr),
+                operator,
+                right: Box::new(right),
+            }
+        }
+
+        Ok(expr)
+    }
+
+    fn bitwise_and(&mut self) -> Result<Expr> {
+        let mut expr = self.comparison()?;
+
+        while self.match_token(&[TokenType::Ampersand]) {
+            let operator = self.previous();
+            let right = self.comparison()?;
+            expr = Expr::Binary {
+                left: Box::new(expr),
+                operator,
+                right: Box::new(right),
+            }
+        }
+
+        Ok(expr)
+    }
+
     fn comparison(&mut self) -> Result<Expr> {
-        let mut expr = self.term()?;
+        let mut expr = self.shift()?;
 
         while self.match_token(&[
             TokenType::Greater,
@@ -401,6 +838,22 @@ impl Parser {
             TokenType::Less,
             TokenType::LessEqual,
         ]) {
+            let operator = self.previous();
+            let right = self.shift()?;
+            expr = Expr::Binary {
+                left: Box::new(expr),
+                operator,
+                right: Box::new(right),
+            }
+        }
+
+        Ok(expr)
+    }
+
+    fn shift(&mut self) -> Result<Expr> {
+        let mut expr = self.term()?;
+
+        while self.match_token(&[TokenType::LessLess, TokenType::GreaterGreater]) {
             let operator = self.previous();
             let right = self.term()?;
             expr = Expr::Binary {
@@ -430,11 +883,11 @@ impl Parser {
     }
 
     fn factor(&mut self) -> Result<Expr> {
-        let mut expr = self.unary()?;
+        let mut expr = self.exponent()?;
 
-        while self.match_token(&[TokenType::Slash, TokenType::Star]) {
+        while self.match_token(&[TokenType::Slash, TokenType::Star, TokenType::Div]) {
             let operator = self.previous();
-            let right = self.unary()?;
+            let right = self.exponent()?;
             expr = Expr::Binary {
                 left: Box::new(expr),
                 operator,
@@ -445,8 +898,26 @@ impl Parser {
         Ok(expr)
     }
 
+    // Unary operators bind tighter than `**`, so `-2 ** 2` is `(-2) ** 2`, which is 4
+    fn exponent(&mut self) -> Result<Expr> {
+        let expr = self.unary()?;
+
+        if self.match_token(&[TokenType::StarStar]) {
+            let operator = self.previous();
+            // Recurse so that `**` is right-associative: `2 ** 3 ** 2` is `2 ** (3 ** 2)`
+            let right = self.exponent()?;
+            Ok(Expr::Binary {
+                left: Box::new(expr),
+                operator,
+                right: Box::new(right),
+            })
+        } else {
+            Ok(expr)
+        }
+    }
+
     fn unary(&mut self) -> Result<Expr> {
-        if self.match_token(&[TokenType::Bang, TokenType::Minus]) {
+        if self.match_token(&[TokenType::Bang, TokenType::Minus, TokenType::Tilde]) {
             let operator = self.previous();
             let right = self.unary()?;
             Ok(Expr::Unary {
@@ -465,11 +936,19 @@ impl Parser {
             if self.match_token(&[TokenType::LeftParen]) {
                 expr = self.finish_call(expr)?;
             } else if self.match_token(&[TokenType::Dot]) {
-                let name = self.consume_identifier("Expect property name after '.'.")?;
+                let name = self.consume_property_name("Expect property name after '.'.")?;
                 expr = Expr::Get {
                     object: Box::new(expr),
                     name,
                 }
+            } else if self.match_token(&[TokenType::LeftBracket]) {
+                let index = self.expression()?;
+                let bracket = self.consume(&TokenType::RightBracket, "Expect ']' after index.")?;
+                expr = Expr::Index {
+                    object: Box::new(expr),
+                    index: Box::new(index),
+                    bracket,
+                }
             } else {
                 break;
             }
@@ -487,13 +966,14 @@ impl Parser {
                 if arguments.len() >= 255 {
                     return Err(Error::TooManyArguments {
                         line: self.peek().get_line(),
+                        column: self.peek().get_column(),
                     });
                 }
 
                 arguments.push(self.expression()?);
 
-                // If there are no more arguments, break
-                if !self.match_token(&[TokenType::Comma]) {
+                // If there are no more arguments, break, allowing a trailing comma
+                if !self.match_token(&[TokenType::Comma]) || self.check(&TokenType::RightParen) {
                     break;
                 }
             }
@@ -509,6 +989,10 @@ impl Parser {
     }
 
     fn primary(&mut self) -> Result<Expr> {
+        if self.is_at_end() {
+            return Err(self.error("Expect expression."));
+        }
+
         let previous = self.advance();
         match previous.get_token_type() {
             TokenType::False => Ok(Expr::Literal(Literal::Bool(false))),
@@ -526,22 +1010,84 @@ impl Parser {
 
             TokenType::This => Ok(Expr::This(previous)),
 
+            TokenType::Loop => self.loop_expression(),
+
+            // An anonymous function, which can be called and operated on like any other
+            // value, e.g. `fn (a) { return a; }(2) + 1`
+            TokenType::Fn => self.lambda(),
+
             TokenType::Identifier(_) => Ok(Expr::Variable(previous)),
 
             TokenType::LeftParen => {
-                let expr = self.expression()?;
+                let mut expr = self.expression()?;
+                if self.check(&TokenType::Comma) {
+                    let mut exprs = vec![expr];
+                    while self.match_token(&[TokenType::Comma]) {
+                        exprs.push(self.expression()?);
+                    }
+                    expr = Expr::Comma(exprs);
+                }
                 self.consume(&TokenType::RightParen, "Expect ')' after expression.")?;
                 Ok(Expr::Grouping(Box::new(expr)))
             }
 
-            _ => Err(Error::Syntax {
-                msg: "Expect expression.".to_owned(),
-                line: previous.get_line(),
-            }),
+            TokenType::LeftBracket => {
+                let mut elements = Vec::new();
+
+                // Parse elements if there are any
+                if !self.check(&TokenType::RightBracket) {
+                    loop {
+                        elements.push(self.expression()?);
+
+                        // If there are no more elements, break, allowing a trailing comma
+                        if !self.match_token(&[TokenType::Comma])
+                            || self.check(&TokenType::RightBracket)
+                        {
+                            break;
+                        }
+                    }
+                }
+
+                self.consume(&TokenType::RightBracket, "Expect ']' after list elements.")?;
+                Ok(Expr::ListLiteral(elements))
+            }
+
+            // A brace can only start a map here, since a brace starting a statement is a block
+            TokenType::LeftBrace => {
+                let mut entries = Vec::new();
+
+                // Parse entries if there are any, allowing a trailing comma like lists
+                if !self.check(&TokenType::RightBrace) {
+                    loop {
+                        let key = self.expression()?;
+                        self.consume(&TokenType::Colon, "Expect ':' after map key.")?;
+                        entries.push((key, self.expression()?));
+
+                        if !self.match_token(&[TokenType::Comma])
+                            || self.check(&TokenType::RightBrace)
+                        {
+                            break;
+                        }
+                    }
+                }
+
+                self.consume(&TokenType::RightBrace, "Expect '}' after map entries.")?;
+                Ok(Expr::MapLiteral {
+                    brace: previous,
+                    entries,
+                })
+            }
+
+            _ => {
+                // Leave the token unconsumed like a failed `consume` does, so recovery
+                // doesn't skip past a `;` it has already seen
+                self.restore();
+                Err(self.error("Expect expression."))
+            }
         }
     }
 
-    fn parameters(&mut self) -> Result<Vec<Token>> {
+    fn parameters(&mut self) -> Result<Vec<Rc<Token>>> {
         // Parse parameters, if any
         let mut params = Vec::new();
         if !self.check(&TokenType::RightParen) {
@@ -549,13 +1095,14 @@ impl Parser {
                 if params.len() >= 255 {
                     return Err(Error::TooManyArguments {
                         line: self.peek().get_line(),
+                        column: self.peek().get_column(),
                     });
                 }
 
                 params.push(self.consume_identifier("Expect parameter name.")?);
 
-                // If there are no more parameters, break out of the loop
-                if !self.match_token(&[TokenType::Comma]) {
+                // If there are no more parameters, break out of the loop, allowing a trailing comma
+                if !self.match_token(&[TokenType::Comma]) || self.check(&TokenType::RightParen) {
                     break;
                 }
             }
@@ -579,9 +1126,12 @@ impl Parser {
                 TokenType::Class
                     | TokenType::Fn
                     | TokenType::Var
+                    | TokenType::Const
                     | TokenType::For
                     | TokenType::If
                     | TokenType::While
+                    | TokenType::Switch
+                    | TokenType::Match
                     | TokenType::Print
                     | TokenType::Return
             ) {
@@ -607,28 +1157,41 @@ impl Parser {
         &mut self,
         token_type: &TokenType,
         message: &S,
-    ) -> Result<Token> {
+    ) -> Result<Rc<Token>> {
         if self.check(token_type) {
             Ok(self.advance())
         } else {
-            Err(Error::Syntax {
-                msg: message.to_string(),
-                line: self.peek().get_line(),
-            })
+            Err(self.error(message))
         }
     }
 
-    fn consume_identifier<S: ToString + ?Sized>(&mut self, message: &S) -> Result<Token> {
-        let error = Error::Syntax {
-            msg: message.to_string(),
-            line: self.peek().get_line(),
-        };
-        if self.is_at_end() {
-            return Err(error);
-        }
+    fn consume_identifier<S: ToString + ?Sized>(&mut self, message: &S) -> Result<Rc<Token>> {
         match self.peek().get_token_type() {
             TokenType::Identifier(_) => Ok(self.advance()),
-            _ => Err(error),
+            _ => Err(self.error(message)),
+        }
+    }
+
+    /// Like `consume_identifier`, but also accepts keywords, which are unambiguous as
+    /// property names after a `.`, e.g. `node.class`.
+    fn consume_property_name<S: ToString + ?Sized>(&mut self, message: &S) -> Result<Rc<Token>> {
+        if KEYWORDS.contains_key(&self.peek().get_lexeme()) {
+            return Ok(self.advance());
+        }
+        self.consume_identifier(message)
+    }
+
+    /// Creates a syntax error at the current token, naming the token that was found there,
+    /// or an unexpected EOF error if the input ran out.
+    fn error<S: ToString + ?Sized>(&self, message: &S) -> Error {
+        let line = self.peek().get_line();
+        let column = self.peek().get_column();
+        if self.is_at_end() {
+            let msg = message.to_string();
+            Error::UnexpectedEof { msg, line, column }
+        } else {
+            let msg = found(&message.to_string(), &self.peek());
+            Error::Syntax { msg, line, column }
         }
     }
 
@@ -639,24 +1202,48 @@ impl Parser {
         self.peek().get_token_type() == *token_type
     }
 
-    fn advance(&mut self) -> Token {
+    /// Checks the token after the current one, without consuming anything.
+    fn check_next(&self, token_type: &TokenType) -> bool {
+        match self.tokens.get(self.current + 1) {
+            Some(token) => token.get_token_type() == *token_type,
+            None => false,
+        }
+    }
+
+    fn advance(&mut self) -> Rc<Token> {
         if !self.is_at_end() {
             self.current += 1;
+            self.fill();
         }
         self.previous()
     }
 
+    /// Pulls tokens from the scanner until the current token and the one after it are
+    /// available, or the scanner has run out after its EOF token.
+    fn fill(&mut self) {
+        let Some(scanner) = &mut self.scanner else {
+            return;
+        };
+        while self.tokens.len() < self.current + 2 {
+            match scanner.next_token() {
+                Some(Ok(token)) => self.tokens.push(Rc::new(token)),
+                Some(Err(error)) => self.scanner_errors.push(error),
+                None => break,
+            }
+        }
+    }
+
     fn restore(&mut self) {
         if self.current > 0 {
             self.current -= 1;
         }
     }
 
-    fn peek(&self) -> Token {
+    fn peek(&self) -> Rc<Token> {
         self.tokens[self.current].clone()
     }
 
-    fn previous(&self) -> Token {
+    fn previous(&self) -> Rc<Token> {
         self.tokens[self.current - 1].clone()
     }
 
@@ -664,3 +1251,165 @@ impl Parser {
         self.peek().get_token_type() == TokenType::Eof
     }
 }
+
+/// Adds the offending token to an error message, e.g. `Expect ';' after value, found '}'.`
+fn found(message: &str, token: &Token) -> String {
+    let message = message.strip_suffix('.').unwrap_or(message);
+    format!("{message}, found '{}'.", token.get_lexeme())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Expr, Parser, Scanner, Stmt};
+    use crate::loxide::ast_printer::AstPrinter;
+
+    fn parse(source: &str) -> Vec<Stmt> {
+        let tokens = Scanner::new(source.into()).scan_tokens().unwrap();
+        Parser::new(tokens).parse().unwrap()
+    }
+
+    #[test]
+    fn anonymous_functions_can_be_called_and_operated_on() {
+        let statements = parse("var w = fn (a) { return a; }(2) + 1;");
+        let [Stmt::Var {
+            initializer: Some(Expr::Binary { left, .. }),
+            ..
+        }] = &statements[..]
+        else {
+            panic!("Expected a binary initializer, got {statements:?}");
+        };
+        let Expr::Call { callee, .. } = left.as_ref() else {
+            panic!("Expected a call, got {left:?}");
+        };
+        assert!(matches!(callee.as_ref(), Expr::Lambda(_)));
+    }
+
+    #[test]
+    fn anonymous_functions_are_values_anywhere() {
+        let statements = parse("f = fn () {}; g(fn (x) { return x; }, 1); fn () {}();");
+        assert!(matches!(
+            &statements[..],
+            [
+                Stmt::Expression(Expr::Assign { .. }),
+                Stmt::Expression(Expr::Call { .. }),
+                Stmt::Expression(Expr::Call { .. }),
+            ]
+        ));
+    }
+
+    #[test]
+    fn streaming_parses_like_batch() {
+        let source = "
+            fn add(a, b) { return a + b; }
+            class A { get { return 1; } }
+            for (var i = 0; i < 3; i = i + 1) print add(i, A().get);
+        ";
+        let streamed = Parser::streaming(Scanner::new(source.into()))
+            .parse()
+            .unwrap();
+        assert_eq!(streamed, parse(source));
+    }
+
+    #[test]
+    fn streaming_collects_scanner_errors() {
+        let mut parser = Parser::streaming(Scanner::new(b"print 1 @;".to_vec()));
+        assert_eq!(parser.parse().unwrap(), parse("print 1;"));
+        assert_eq!(parser.take_scanner_errors().len(), 1);
+    }
+
+    #[test]
+    fn only_instance_methods_can_be_getters() {
+        let statements = parse("class A { area { return 1; } class make() { return A(); } }");
+        let [Stmt::Class {
+            methods,
+            static_methods,
+            ..
+        }] = &statements[..]
+        else {
+            panic!("Expected a class, got {statements:?}");
+        };
+        assert!(methods[0].is_getter);
+        assert!(!static_methods[0].is_getter);
+
+        let tokens = Scanner::new(b"class A { class make { return A(); } }".to_vec())
+            .scan_tokens()
+            .unwrap();
+        let errors = Parser::new(tokens).parse().unwrap_err();
+        assert_eq!(
+            errors[0].to_string(),
+            "[line 1] Expect '(' after static method name, found '{'."
+        );
+    }
+
+    #[test]
+    fn lists_of_arguments_parameters_and_elements_allow_a_trailing_comma() {
+        let print = |source| AstPrinter.print(&parse(source));
+        assert_eq!(
+            print("f(\n  1,\n  g(2,),\n);\nfn h(a, b,) {}\nprint [1,];\nprint {1: 2,};"),
+            print("f(1, g(2));\nfn h(a, b) {}\nprint [1];\nprint {1: 2};")
+        );
+
+        let tokens = Scanner::new(b"f(,); fn h(a,,) {}".to_vec())
+            .scan_tokens()
+            .unwrap();
+        let errors = Parser::new(tokens).parse().unwrap_err();
+        assert_eq!(errors.len(), 2);
+    }
+
+    #[test]
+    fn syntax_errors_name_the_token_they_found() {
+        let first_error = |source: &str| {
+            let tokens = Scanner::new(source.into()).scan_tokens().unwrap();
+            Parser::new(tokens).parse().unwrap_err()[0].to_string()
+        };
+        assert_eq!(
+            first_error("var x = 1 print x;"),
+            "[line 1] Expect ';' after variable declaration, found 'print'."
+        );
+        assert_eq!(
+            first_error("if (true) print 1; else else print 2;"),
+            "[line 1] Expect expression, found 'else'."
+        );
+        assert_eq!(
+            first_error("fn f(a, 1) {}"),
+            "[line 1] Expect parameter name, found '1'."
+        );
+        assert_eq!(
+            first_error("switch (1) { print 1; }"),
+            "[line 1] Expect 'case' or 'default' in 'switch' body, found 'print'."
+        );
+
+        // Running out of input has no token to name
+        assert_eq!(
+            first_error("var x = 1"),
+            "[line 1] Expect ';' after variable declaration."
+        );
+    }
+
+    #[test]
+    fn recovering_keeps_the_declarations_around_errors() {
+        let source = "var a = 1;\nvar b = ;\nprint a;\nfn f( {}\nfn g() { return 2; }";
+        let tokens = Scanner::new(source.into()).scan_tokens().unwrap();
+        let (statements, errors) = Parser::new(tokens).parse_recovering();
+        assert_eq!(
+            AstPrinter.print(&statements),
+            "(var a 1)\n(print a)\n(fn g () (return 2))\n"
+        );
+        let errors = errors.iter().map(ToString::to_string).collect::<Vec<_>>();
+        assert_eq!(
+            errors,
+            [
+                "[line 2] Expect expression, found ';'.",
+                "[line 4] Expect parameter name, found '{'.",
+            ]
+        );
+
+        // Without errors, it's the same as parsing
+        let tokens = Scanner::new("var a = 1; print a;".into())
+            .scan_tokens()
+            .unwrap();
+        let (statements, errors) = Parser::new(tokens).parse_recovering();
+        assert_eq!(statements, parse("var a = 1; print a;"));
+        assert!(errors.is_empty());
+    }
+}
@@ -1,50 +1,206 @@
-use std::collections::HashMap;
+use std::{
+    collections::{BTreeSet, HashMap, HashSet},
+    rc::Rc,
+};
 
 use thiserror::Error;
 
 use super::{
-    ast::{Expr, Stmt, Visitor},
+    ast::{Expr, NodeId, Stmt, Visitor},
     interpreter::functions::FunctionDeclaration,
+    symbol::Symbol,
     token::Token,
 };
 
 #[derive(Debug, Error)]
 pub enum Error {
-    #[error("Can't read local variable in its own initializer.")]
-    SelfReferencedInitializer,
-
-    #[error("A variable with name `{name}` was already declared in this scope.")]
-    VariableAlreadyDeclared { name: String },
+    #[error("[line {line}] Can't read local variable in its own initializer.")]
+    SelfReferencedInitializer { line: usize, column: usize },
+
+    #[error("[line {line}] A variable with name `{name}` was already declared in this scope.")]
+    VariableAlreadyDeclared {
+        name: String,
+        line: usize,
+        column: usize,
+    },
+
+    #[error("[line {line}] Can't assign to constant `{name}`.")]
+    AssignToConst {
+        name: String,
+        line: usize,
+        column: usize,
+    },
+
+    #[error("[line {line}] Parameter `{name}` is declared more than once.")]
+    DuplicateParameter {
+        name: String,
+        line: usize,
+        column: usize,
+    },
+
+    #[error("[line {line}] Can't return from top-level code.")]
+    ReturnOutsideFunction { line: usize, column: usize },
+
+    #[error("[line {line}] Can't use `break` outside of a loop.")]
+    BreakOutsideLoop { line: usize, column: usize },
+
+    #[error("[line {line}] Can't break with a value outside of a `loop` expression.")]
+    BreakValueOutsideLoop { line: usize, column: usize },
+
+    #[error("[line {line}] Can't use `continue` outside of a loop.")]
+    ContinueOutsideLoop { line: usize, column: usize },
+
+    #[error("[line {line}] Can't return a value from an initializer.")]
+    ReturnFromInitializer { line: usize, column: usize },
+
+    #[error("[line {line}] Can't use `this` outside of a class.")]
+    ThisOutsideClass { line: usize, column: usize },
+
+    #[error("[line {line}] Can't use `this` in a static method.")]
+    ThisInStaticMethod { line: usize, column: usize },
+
+    #[error("[line {line}] Class {name} can't inherit from itself.")]
+    ClassInheritanceCycle {
+        name: String,
+        line: usize,
+        column: usize,
+    },
+
+    #[error("[line {line}] Can't use `super` outside of a class.")]
+    SuperOutsideClass { line: usize, column: usize },
+
+    #[error("[line {line}] Can't use `super` in a static method.")]
+    SuperInStaticMethod { line: usize, column: usize },
+
+    #[error("[line {line}] Can't use `super` in a class with no superclass.")]
+    SuperWithoutSuperclass { line: usize, column: usize },
+}
 
-    #[error("Can't return from top-level code.")]
-    ReturnOutsideFunction,
+impl Error {
+    /// The name of the variant, e.g. `ReturnOutsideFunction`.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::SelfReferencedInitializer { .. } => "SelfReferencedInitializer",
+            Self::VariableAlreadyDeclared { .. } => "VariableAlreadyDeclared",
+            Self::AssignToConst { .. } => "AssignToConst",
+            Self::DuplicateParameter { .. } => "DuplicateParameter",
+            Self::ReturnOutsideFunction { .. } => "ReturnOutsideFunction",
+            Self::BreakOutsideLoop { .. } => "BreakOutsideLoop",
+            Self::BreakValueOutsideLoop { .. } => "BreakValueOutsideLoop",
+            Self::ContinueOutsideLoop { .. } => "ContinueOutsideLoop",
+            Self::ReturnFromInitializer { .. } => "ReturnFromInitializer",
+            Self::ThisOutsideClass { .. } => "ThisOutsideClass",
+            Self::ThisInStaticMethod { .. } => "ThisInStaticMethod",
+            Self::ClassInheritanceCycle { .. } => "ClassInheritanceCycle",
+            Self::SuperOutsideClass { .. } => "SuperOutsideClass",
+            Self::SuperInStaticMethod { .. } => "SuperInStaticMethod",
+            Self::SuperWithoutSuperclass { .. } => "SuperWithoutSuperclass",
+        }
+    }
 
-    #[error("Can't return a value from an initializer.")]
-    ReturnFromInitializer,
+    pub fn line(&self) -> usize {
+        match self {
+            Self::SelfReferencedInitializer { line, .. }
+            | Self::VariableAlreadyDeclared { line, .. }
+            | Self::AssignToConst { line, .. }
+            | Self::DuplicateParameter { line, .. }
+            | Self::ReturnOutsideFunction { line, .. }
+            | Self::BreakOutsideLoop { line, .. }
+            | Self::BreakValueOutsideLoop { line, .. }
+            | Self::ContinueOutsideLoop { line, .. }
+            | Self::ReturnFromInitializer { line, .. }
+            | Self::ThisOutsideClass { line, .. }
+            | Self::ThisInStaticMethod { line, .. }
+            | Self::ClassInheritanceCycle { line, .. }
+            | Self::SuperOutsideClass { line, .. }
+            | Self::SuperInStaticMethod { line, .. }
+            | Self::SuperWithoutSuperclass { line, .. } => *line,
+        }
+    }
 
-    #[error("Can't use `this` outside of a class.")]
-    ThisOutsideClass,
+    pub fn column(&self) -> usize {
+        match self {
+            Self::SelfReferencedInitializer { column, .. }
+            | Self::VariableAlreadyDeclared { column, .. }
+            | Self::AssignToConst { column, .. }
+            | Self::DuplicateParameter { column, .. }
+            | Self::ReturnOutsideFunction { column, .. }
+            | Self::BreakOutsideLoop { column, .. }
+            | Self::BreakValueOutsideLoop { column, .. }
+            | Self::ContinueOutsideLoop { column, .. }
+            | Self::ReturnFromInitializer { column, .. }
+            | Self::ThisOutsideClass { column, .. }
+            | Self::ThisInStaticMethod { column, .. }
+            | Self::ClassInheritanceCycle { column, .. }
+            | Self::SuperOutsideClass { column, .. }
+            | Self::SuperInStaticMethod { column, .. }
+            | Self::SuperWithoutSuperclass { column, .. } => *column,
+        }
+    }
+}
 
-    #[error("Class {name} can't inherit from itself.")]
-    ClassInheritanceCycle { name: String },
+/// Non-fatal diagnostics reported during resolution.
+#[derive(Debug, Error)]
+pub enum Warning {
+    #[error(
+        "[line {line}] Warning: Assignment to `{name}` used as a condition. Did you mean `==`?"
+    )]
+    AssignmentInCondition { name: String, line: usize },
+
+    #[error("[line {line}] Warning: Local variable `{name}` is never used.")]
+    UnusedVariable { name: String, line: usize },
+}
 
-    #[error("Can't use `super` outside of a class.")]
-    SuperOutsideClass,
+type Result<T = (), E = Error> = std::result::Result<T, E>;
 
-    #[error("Can't use `super` in a class with no superclass.")]
-    SuperWithoutSuperclass,
+/// The scope distance and slot within that scope of each resolved local variable expression.
+pub type Locals = HashMap<NodeId, (usize, usize)>;
+
+/// The names of the variables each function captures from enclosing local scopes.
+/// Globals are looked up dynamically and are never considered captured.
+pub type Captures = HashMap<FunctionDeclaration, BTreeSet<String>>;
+
+/// Everything learned about a program during resolution.
+pub struct Resolution {
+    pub locals: Locals,
+    pub captures: Captures,
+    pub warnings: Vec<Warning>,
+    /// The names of variables that aren't found in any local scope and are looked up globally.
+    pub globals: BTreeSet<String>,
+    /// Constants declared at the top level, to pass on when resolving later input in the
+    /// same session.
+    pub global_consts: HashSet<Symbol>,
 }
 
-type Result<T = (), E = Error> = std::result::Result<T, E>;
+/// A variable declared in a local scope.
+struct Variable {
+    /// Whether the initializer has finished, so the variable can be read
+    defined: bool,
+    /// Whether the variable has been referenced, parameters and `this` always count as used
+    used: bool,
+    line: usize,
+    /// The position of the variable in its scope, in declaration order
+    slot: usize,
+    /// Whether the variable was declared with `const`
+    constant: bool,
+}
 
 #[derive(PartialEq, Copy, Clone)]
 enum FnType {
     None,
     Function,
     Method,
+    StaticMethod,
     Initializer,
 }
 
+#[derive(PartialEq, Copy, Clone)]
+enum LoopType {
+    None,
+    While,
+    Loop,
+}
+
 #[derive(PartialEq, Copy, Clone, Debug)]
 enum ClassType {
     None,
@@ -53,10 +209,18 @@ enum ClassType {
 }
 
 pub struct Resolver {
-    scopes: Vec<HashMap<String, bool>>,
-    locals: HashMap<Expr, usize>,
+    scopes: Vec<HashMap<Symbol, Variable>>,
+    locals: Locals,
+    captures: Captures,
+    /// The scope index at which each enclosing function begins, and the names it captures
+    functions: Vec<(usize, BTreeSet<String>)>,
     current_fn: FnType,
     current_class: ClassType,
+    current_loop: LoopType,
+    warnings: Vec<Warning>,
+    globals: BTreeSet<String>,
+    /// Constants declared at the top level, which aren't tracked in any scope
+    global_consts: HashSet<Symbol>,
 }
 
 impl Resolver {
@@ -64,8 +228,23 @@ impl Resolver {
         Self {
             scopes: Vec::new(),
             locals: HashMap::new(),
+            captures: HashMap::new(),
+            functions: Vec::new(),
             current_fn: FnType::None,
             current_class: ClassType::None,
+            current_loop: LoopType::None,
+            warnings: Vec::new(),
+            globals: BTreeSet::new(),
+            global_consts: HashSet::new(),
+        }
+    }
+
+    /// Creates a resolver that knows about constants declared at the top level by earlier
+    /// input, like previous lines in the REPL.
+    pub fn with_global_consts(global_consts: HashSet<Symbol>) -> Self {
+        Self {
+            global_consts,
+            ..Self::new()
         }
     }
 
@@ -74,10 +253,43 @@ impl Resolver {
     }
 
     fn end_scope(&mut self) {
-        self.scopes.pop();
+        if let Some(scope) = self.scopes.pop() {
+            let mut unused = (scope.into_iter())
+                .filter(|(_, variable)| !variable.used)
+                .collect::<Vec<_>>();
+            unused.sort_by_key(|(_, variable)| variable.line);
+
+            self.warnings
+                .extend(
+                    unused
+                        .into_iter()
+                        .map(|(name, variable)| Warning::UnusedVariable {
+                            name: name.to_string(),
+                            line: variable.line,
+                        }),
+                );
+        }
+    }
+
+    /// Adds a variable to the current scope that is never reported as unused.
+    fn define_implicit(&mut self, name: Symbol) {
+        if let Some(scope) = self.scopes.last_mut() {
+            // Keep the slot of a variable that was already declared, like a parameter
+            let slot = scope
+                .get(&name)
+                .map_or(scope.len(), |variable| variable.slot);
+            let variable = Variable {
+                defined: true,
+                used: true,
+                line: 0,
+                slot,
+                constant: false,
+            };
+            scope.insert(name, variable);
+        }
     }
 
-    pub fn run(mut self, statements: &[Stmt]) -> Result<HashMap<Expr, usize>, Vec<Error>> {
+    pub fn run(mut self, statements: &[Stmt]) -> Result<Resolution, Vec<Error>> {
         let mut errors = Vec::new();
         for stmt in statements {
             match self.visit_stmt(stmt) {
@@ -87,7 +299,13 @@ impl Resolver {
         }
 
         if errors.is_empty() {
-            Ok(self.locals)
+            Ok(Resolution {
+                locals: self.locals,
+                captures: self.captures,
+                warnings: self.warnings,
+                globals: self.globals,
+                global_consts: self.global_consts,
+            })
         } else {
             Err(errors)
         }
@@ -97,46 +315,196 @@ impl Resolver {
         statements.iter().try_for_each(|stmt| self.visit_stmt(stmt))
     }
 
-    fn resolve_local(&mut self, expr: &Expr, name: &Token) {
-        for (i, scope) in self.scopes.iter().enumerate().rev() {
-            if scope.contains_key(&name.get_lexeme()) {
-                let distance = self.scopes.len() - 1 - i;
-                self.locals.insert(expr.clone(), distance);
+    fn resolve_local(&mut self, name: &Rc<Token>) {
+        let depth = self.scopes.len();
+        for (i, scope) in self.scopes.iter_mut().enumerate().rev() {
+            if let Some(variable) = scope.get_mut(&name.get_symbol()) {
+                variable.used = true;
+
+                let distance = depth - 1 - i;
+                self.locals
+                    .insert(NodeId::new(name), (distance, variable.slot));
+
+                // Every function that began after the variable's scope captures it
+                for (start, captured) in self.functions.iter_mut() {
+                    if i < *start {
+                        captured.insert(name.get_lexeme());
+                    }
+                }
+                return;
             }
         }
+
+        // Not found in any local scope, so it's looked up as a global
+        self.globals.insert(name.get_lexeme());
     }
 
     fn declare(&mut self, name: &Token) -> Result {
         if let Some(scope) = self.scopes.last_mut() {
-            let lexeme = name.get_lexeme();
-            if scope.contains_key(&lexeme) {
-                return Err(Error::VariableAlreadyDeclared { name: lexeme });
+            let symbol = name.get_symbol();
+            if scope.contains_key(&symbol) {
+                return Err(Error::VariableAlreadyDeclared {
+                    name: symbol.to_string(),
+                    line: name.get_line(),
+                    column: name.get_column(),
+                });
             }
-            scope.insert(lexeme, false);
+            let variable = Variable {
+                defined: false,
+                used: false,
+                line: name.get_line(),
+                slot: scope.len(),
+                constant: false,
+            };
+            scope.insert(symbol, variable);
+        } else {
+            // Redeclaring a global replaces it, so it's only constant if declared with `const`
+            self.global_consts.remove(&name.get_symbol());
         }
         Ok(())
     }
 
     fn define(&mut self, name: &Token) {
-        if let Some(scope) = self.scopes.last_mut() {
-            scope.insert(name.get_lexeme(), true);
+        let scope = self.scopes.last_mut();
+        if let Some(variable) = scope.and_then(|scope| scope.get_mut(&name.get_symbol())) {
+            variable.defined = true;
+        }
+    }
+
+    /// Checks that the variable an assignment resolves to wasn't declared with `const`.
+    fn check_assignable(&self, name: &Token) -> Result {
+        let symbol = name.get_symbol();
+        let constant = match self
+            .scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.get(&symbol))
+        {
+            Some(variable) => variable.constant,
+            None => self.global_consts.contains(&symbol),
+        };
+
+        if constant {
+            return Err(Error::AssignToConst {
+                name: name.get_lexeme(),
+                line: name.get_line(),
+                column: name.get_column(),
+            });
+        }
+        Ok(())
+    }
+
+    fn check_condition(&mut self, condition: &Expr) {
+        // Only a bare assignment is flagged, wrapping it in parentheses silences the warning
+        if let Expr::Assign { name, .. } = condition {
+            self.warnings.push(Warning::AssignmentInCondition {
+                name: name.get_lexeme(),
+                line: name.get_line(),
+            });
         }
     }
 
     fn resolve_function(&mut self, declaration: &FunctionDeclaration, fn_type: FnType) -> Result {
+        // Checked up front, so a repeated parameter isn't reported as a redeclared variable
+        let mut seen = HashSet::new();
+        if let Some(param) =
+            (declaration.params.iter()).find(|param| !seen.insert(param.get_symbol()))
+        {
+            return Err(Error::DuplicateParameter {
+                name: param.get_lexeme(),
+                line: param.get_line(),
+                column: param.get_column(),
+            });
+        }
+
         let enclosing_fn = self.current_fn;
         self.current_fn = fn_type;
 
+        // Loops don't extend into function bodies
+        let enclosing_loop = self.current_loop;
+        self.current_loop = LoopType::None;
+
+        self.functions.push((self.scopes.len(), BTreeSet::new()));
         self.begin_scope();
-        for param in &declaration.params {
-            self.declare(param)?;
-            self.define(param);
-        }
-        self.resolve(&declaration.body)?;
+        let result = declaration
+            .params
+            .iter()
+            .try_for_each(|param| {
+                self.declare(param)?;
+                self.define_implicit(param.get_symbol());
+                Ok(())
+            })
+            .and_then(|_| self.resolve(&declaration.body));
         self.end_scope();
 
+        if let Some((_, captured)) = self.functions.pop() {
+            self.captures.insert(declaration.clone(), captured);
+        }
+
+        self.current_loop = enclosing_loop;
         self.current_fn = enclosing_fn;
-        Ok(())
+        result
+    }
+
+    /// Resolves the superclass and methods of a class. The caller restores `current_class`
+    /// afterwards, while the scopes for `super` and `this` are always ended here.
+    fn resolve_class(
+        &mut self,
+        name: &Token,
+        superclass: Option<&Expr>,
+        methods: &[FunctionDeclaration],
+        static_methods: &[FunctionDeclaration],
+    ) -> Result {
+        self.current_class = ClassType::Class;
+
+        if let Some(superclass) = superclass {
+            match superclass {
+                Expr::Variable(token) => {
+                    if name.get_lexeme() == token.get_lexeme() {
+                        return Err(Error::ClassInheritanceCycle {
+                            name: name.get_lexeme(),
+                            line: token.get_line(),
+                            column: token.get_column(),
+                        });
+                    }
+                }
+                _ => unreachable!("Superclass should be a variable expression"),
+            }
+
+            self.current_class = ClassType::Subclass;
+            self.visit_expr(superclass)?;
+
+            self.begin_scope(); // Add a scope for the superclass
+            self.define_implicit(Symbol::intern("super"));
+        }
+
+        // Add a scope for class methods
+        self.begin_scope();
+        // Bind `this` to the class
+        self.define_implicit(Symbol::intern("this"));
+
+        let result = methods.iter().try_for_each(|method| {
+            let fn_type = if method.name.get_lexeme() == "init" {
+                FnType::Initializer
+            } else {
+                FnType::Method
+            };
+            self.resolve_function(method, fn_type)
+        });
+
+        self.end_scope(); // End the scope for class methods
+
+        // End the scope for the superclass
+        if superclass.is_some() {
+            self.end_scope();
+        }
+        result?;
+
+        // Static methods have no instance, so they're resolved outside of the class
+        // scopes where `this` and `super` are bound
+        self.current_class = ClassType::None;
+        (static_methods.iter())
+            .try_for_each(|method| self.resolve_function(method, FnType::StaticMethod))
     }
 }
 
@@ -145,17 +513,21 @@ impl Visitor<Result, Result> for Resolver {
         match expr {
             Expr::Variable(name) => {
                 if let Some(scope) = self.scopes.last() {
-                    if let Some(false) = scope.get(&name.get_lexeme()) {
-                        return Err(Error::SelfReferencedInitializer);
+                    if let Some(false) = scope.get(&name.get_symbol()).map(|v| v.defined) {
+                        return Err(Error::SelfReferencedInitializer {
+                            line: name.get_line(),
+                            column: name.get_column(),
+                        });
                     }
                 }
-                self.resolve_local(expr, name);
+                self.resolve_local(name);
                 Ok(())
             }
 
             Expr::Assign { name, value } => {
+                self.check_assignable(name)?;
                 self.visit_expr(value)?;
-                self.resolve_local(expr, name);
+                self.resolve_local(name);
                 Ok(())
             }
 
@@ -164,6 +536,16 @@ impl Visitor<Result, Result> for Resolver {
                 self.visit_expr(right)
             }
 
+            Expr::Ternary {
+                condition,
+                then_branch,
+                else_branch,
+            } => {
+                self.visit_expr(condition)?;
+                self.visit_expr(then_branch)?;
+                self.visit_expr(else_branch)
+            }
+
             Expr::Call {
                 callee, arguments, ..
             } => {
@@ -173,12 +555,33 @@ impl Visitor<Result, Result> for Resolver {
 
             Expr::Grouping(expr) => self.visit_expr(expr),
 
+            Expr::Comma(exprs) => exprs.iter().try_for_each(|expr| self.visit_expr(expr)),
+
             Expr::Literal(_) => Ok(()),
 
+            Expr::ListLiteral(elements) => elements
+                .iter()
+                .try_for_each(|element| self.visit_expr(element)),
+
+            Expr::MapLiteral { entries, .. } => entries.iter().try_for_each(|(key, value)| {
+                self.visit_expr(key)?;
+                self.visit_expr(value)
+            }),
+
             Expr::Unary { right, .. } => self.visit_expr(right),
 
             Expr::Lambda(declaration) => self.resolve_function(declaration, FnType::Function),
 
+            Expr::Loop(body) => {
+                let enclosing_loop = self.current_loop;
+                self.current_loop = LoopType::Loop;
+                self.begin_scope();
+                let result = self.resolve(body);
+                self.end_scope();
+                self.current_loop = enclosing_loop;
+                result
+            }
+
             Expr::Get { object, .. } => self.visit_expr(object),
 
             Expr::Set { object, value, .. } => {
@@ -186,21 +589,43 @@ impl Visitor<Result, Result> for Resolver {
                 self.visit_expr(value)
             }
 
+            Expr::Index { object, index, .. } => {
+                self.visit_expr(object)?;
+                self.visit_expr(index)
+            }
+
+            Expr::SetIndex {
+                object,
+                index,
+                value,
+                ..
+            } => {
+                self.visit_expr(object)?;
+                self.visit_expr(index)?;
+                self.visit_expr(value)
+            }
+
             Expr::This(keyword) => {
-                if self.current_class == ClassType::None {
-                    return Err(Error::ThisOutsideClass);
+                let (line, column) = (keyword.get_line(), keyword.get_column());
+                if self.current_fn == FnType::StaticMethod {
+                    return Err(Error::ThisInStaticMethod { line, column });
+                } else if self.current_class == ClassType::None {
+                    return Err(Error::ThisOutsideClass { line, column });
                 }
-                self.resolve_local(expr, keyword);
+                self.resolve_local(keyword);
                 Ok(())
             }
 
             Expr::Super { keyword, .. } => {
-                if self.current_class == ClassType::None {
-                    Err(Error::SuperOutsideClass)
+                let (line, column) = (keyword.get_line(), keyword.get_column());
+                if self.current_fn == FnType::StaticMethod {
+                    Err(Error::SuperInStaticMethod { line, column })
+                } else if self.current_class == ClassType::None {
+                    Err(Error::SuperOutsideClass { line, column })
                 } else if self.current_class != ClassType::Subclass {
-                    Err(Error::SuperWithoutSuperclass)
+                    Err(Error::SuperWithoutSuperclass { line, column })
                 } else {
-                    self.resolve_local(expr, keyword);
+                    self.resolve_local(keyword);
                     Ok(())
                 }
             }
@@ -209,11 +634,13 @@ impl Visitor<Result, Result> for Resolver {
 
     fn visit_stmt(&mut self, stmt: &Stmt) -> Result {
         match stmt {
+            // Scopes are always ended, even if resolving inside them fails, so later statements
+            // aren't resolved in them
             Stmt::Block(statements) => {
                 self.begin_scope();
-                self.resolve(statements)?;
+                let result = self.resolve(statements);
                 self.end_scope();
-                Ok(())
+                result
             }
 
             Stmt::Var { name, initializer } => {
@@ -225,19 +652,38 @@ impl Visitor<Result, Result> for Resolver {
                 Ok(())
             }
 
+            Stmt::Const { name, initializer } => {
+                self.declare(name)?;
+                self.visit_expr(initializer)?;
+                self.define(name);
+
+                match self.scopes.last_mut() {
+                    Some(scope) => {
+                        if let Some(variable) = scope.get_mut(&name.get_symbol()) {
+                            variable.constant = true;
+                        }
+                    }
+                    None => {
+                        self.global_consts.insert(name.get_symbol());
+                    }
+                }
+                Ok(())
+            }
+
             Stmt::Function(declaration) => {
                 self.declare(&declaration.name)?;
                 self.define(&declaration.name);
                 self.resolve_function(declaration, FnType::Function)
             }
 
-            Stmt::Expression(expr) | Stmt::Print(expr) => self.visit_expr(expr),
+            Stmt::Expression(expr) | Stmt::Print { value: expr, .. } => self.visit_expr(expr),
 
             Stmt::If {
                 condition,
                 then_branch,
                 else_branch,
             } => {
+                self.check_condition(condition);
                 self.visit_expr(condition)?;
                 self.visit_stmt(then_branch)?;
                 if let Some(else_branch) = else_branch {
@@ -246,14 +692,15 @@ impl Visitor<Result, Result> for Resolver {
                 Ok(())
             }
 
-            Stmt::Return { value, .. } => {
+            Stmt::Return { keyword, value } => {
+                let (line, column) = (keyword.get_line(), keyword.get_column());
                 if self.current_fn == FnType::None {
-                    return Err(Error::ReturnOutsideFunction);
+                    return Err(Error::ReturnOutsideFunction { line, column });
                 }
 
                 if let Some(value) = value {
                     if self.current_fn == FnType::Initializer {
-                        return Err(Error::ReturnFromInitializer);
+                        return Err(Error::ReturnFromInitializer { line, column });
                     }
 
                     self.visit_expr(value)?;
@@ -261,75 +708,242 @@ impl Visitor<Result, Result> for Resolver {
                 Ok(())
             }
 
-            Stmt::While { condition, body } => {
+            Stmt::While {
+                condition,
+                body,
+                increment,
+                ..
+            } => {
+                self.check_condition(condition);
                 self.visit_expr(condition)?;
-                self.visit_stmt(body)
-            }
 
-            Stmt::Break => Ok(()),
+                let enclosing_loop = self.current_loop;
+                self.current_loop = LoopType::While;
+                let result = self.visit_stmt(body);
+                self.current_loop = enclosing_loop;
+                result?;
 
-            Stmt::Class {
+                if let Some(increment) = increment {
+                    self.visit_expr(increment)?;
+                }
+                Ok(())
+            }
+
+            Stmt::ForIn {
                 name,
-                superclass,
-                methods,
+                iterable,
+                body,
             } => {
-                let enclosing_class = self.current_class;
-                self.current_class = ClassType::Class;
+                self.visit_expr(iterable)?;
 
-                self.declare(name)?;
-                self.define(name);
+                // The loop variable lives in its own scope around the body
+                self.begin_scope();
+                let enclosing_loop = self.current_loop;
+                self.current_loop = LoopType::While;
+                let result = self.declare(name).and_then(|_| {
+                    self.define(name);
+                    self.visit_stmt(body)
+                });
+                self.current_loop = enclosing_loop;
 
-                if let Some(superclass) = superclass {
-                    match superclass {
-                        Expr::Variable(token) => {
-                            if name.get_lexeme() == token.get_lexeme() {
-                                return Err(Error::ClassInheritanceCycle {
-                                    name: name.get_lexeme(),
-                                });
-                            }
-                        }
-                        _ => unreachable!("Superclass should be a variable expression"),
-                    }
+                self.end_scope();
+                result
+            }
 
-                    self.current_class = ClassType::Subclass;
-                    self.visit_expr(superclass)?;
+            Stmt::Switch {
+                subject,
+                cases,
+                default,
+                ..
+            } => {
+                self.visit_expr(subject)?;
+                for (value, body) in cases {
+                    self.visit_expr(value)?;
+                    self.begin_scope();
+                    let result = self.resolve(body);
+                    self.end_scope();
+                    result?;
+                }
+                if let Some(body) = default {
+                    self.begin_scope();
+                    let result = self.resolve(body);
+                    self.end_scope();
+                    result?;
+                }
+                Ok(())
+            }
 
-                    self.begin_scope(); // Add a scope for the superclass
-                    if let Some(scope) = self.scopes.last_mut() {
-                        scope.insert("super".to_string(), true);
-                    } else {
-                        unreachable!("No scope for superclass");
+            Stmt::Match {
+                subject,
+                arms,
+                else_branch,
+            } => {
+                self.visit_expr(subject)?;
+                for arm in arms {
+                    // The binding lives in its own scope around the arm's body
+                    self.begin_scope();
+                    let result = match &arm.binding {
+                        Some(binding) => self.declare(binding).map(|_| self.define(binding)),
+                        None => Ok(()),
                     }
+                    .and_then(|_| self.visit_stmt(&arm.body));
+                    self.end_scope();
+                    result?;
                 }
-
-                // Add a scope for class methods
-                self.begin_scope();
-                // Bind `this` to the class
-                if let Some(scope) = self.scopes.last_mut() {
-                    scope.insert("this".to_string(), true);
-                } else {
-                    unreachable!("No scope for class methods");
+                if let Some(body) = else_branch {
+                    self.begin_scope();
+                    let result = self.visit_stmt(body);
+                    self.end_scope();
+                    result?;
                 }
+                Ok(())
+            }
 
-                for method in methods {
-                    let fn_type = if method.name.get_lexeme() == "init" {
-                        FnType::Initializer
-                    } else {
-                        FnType::Method
-                    };
-                    self.resolve_function(method, fn_type)?;
+            Stmt::Break { keyword, value } => {
+                let (line, column) = (keyword.get_line(), keyword.get_column());
+                if self.current_loop == LoopType::None {
+                    return Err(Error::BreakOutsideLoop { line, column });
                 }
 
-                self.end_scope(); // End the scope for class methods
+                if let Some(value) = value {
+                    if self.current_loop != LoopType::Loop {
+                        return Err(Error::BreakValueOutsideLoop { line, column });
+                    }
+                    self.visit_expr(value)?;
+                }
+                Ok(())
+            }
 
-                // End the scope for the superclass
-                if superclass.is_some() {
-                    self.end_scope();
+            Stmt::Continue { keyword } => {
+                if self.current_loop == LoopType::None {
+                    return Err(Error::ContinueOutsideLoop {
+                        line: keyword.get_line(),
+                        column: keyword.get_column(),
+                    });
                 }
+                Ok(())
+            }
+
+            Stmt::Comment { .. } => Ok(()),
 
+            Stmt::Class {
+                name,
+                superclass,
+                methods,
+                static_methods,
+            } => {
+                self.declare(name)?;
+                self.define(name);
+
+                let enclosing_class = self.current_class;
+                let result = self.resolve_class(name, superclass.as_ref(), methods, static_methods);
                 self.current_class = enclosing_class;
-                Ok(())
+                result
             }
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{Error, Resolution, Resolver};
+    use crate::loxide::{parser::Parser, scanner::Scanner};
+
+    fn resolve(source: &str) -> Result<Resolution, Vec<Error>> {
+        let tokens = Scanner::new(source.into()).scan_tokens().unwrap();
+        let statements = Parser::new(tokens).parse().unwrap();
+        Resolver::new().run(&statements)
+    }
+
+    /// The kinds of the errors a program fails to resolve with, or none if it resolves.
+    fn errors(source: &str) -> Vec<&'static str> {
+        resolve(source).map_or_else(|e| e.iter().map(Error::kind).collect(), |_| Vec::new())
+    }
+
+    /// The messages of the warnings about a program that resolves.
+    fn warnings(source: &str) -> Vec<String> {
+        let resolution = resolve(source).unwrap_or_else(|e| panic!("{source:?} failed: {e:?}"));
+        (resolution.warnings.iter())
+            .map(ToString::to_string)
+            .collect()
+    }
+
+    #[test]
+    fn assignments_used_as_conditions_are_warned_about() {
+        assert_eq!(
+            warnings("var x = 1;\nif (x = 5) print x;\nwhile (x = nil) {}"),
+            [
+                "[line 2] Warning: Assignment to `x` used as a condition. Did you mean `==`?",
+                "[line 3] Warning: Assignment to `x` used as a condition. Did you mean `==`?",
+            ]
+        );
+        assert!(warnings("var x = 1;\nif (x == 5) print x;").is_empty());
+        assert!(warnings("var x = 1;\nif ((x = 5) != nil) print x;").is_empty());
+    }
+
+    #[test]
+    fn break_and_continue_must_be_in_a_loop() {
+        assert_eq!(errors("break;"), ["BreakOutsideLoop"]);
+        assert_eq!(errors("continue;"), ["ContinueOutsideLoop"]);
+        assert_eq!(
+            errors("while (true) { fn f() { break; } }"),
+            ["BreakOutsideLoop"]
+        );
+        assert_eq!(errors("while (true) break 1;"), ["BreakValueOutsideLoop"]);
+        assert!(errors("while (true) { for (;;) { continue; } break; }").is_empty());
+        assert!(errors("var x = loop { while (true) break; break 1; };").is_empty());
+    }
+
+    #[test]
+    fn classes_cannot_inherit_from_themselves() {
+        assert_eq!(errors("class A < A {}"), ["ClassInheritanceCycle"]);
+        assert!(errors("class A {} class B < A {}").is_empty());
+    }
+
+    #[test]
+    fn static_methods_cannot_use_this_or_super() {
+        assert_eq!(
+            errors("class A { class f() { return this; } }"),
+            ["ThisInStaticMethod"]
+        );
+        assert_eq!(
+            errors("class A { f() {} } class B < A { class g() { super.f(); } }"),
+            ["SuperInStaticMethod"]
+        );
+    }
+
+    #[test]
+    fn parameters_must_have_distinct_names() {
+        assert_eq!(errors("fn f(a, a) {}"), ["DuplicateParameter"]);
+        assert_eq!(errors("fn f(a, b, a, a) {}"), ["DuplicateParameter"]);
+        assert_eq!(
+            errors("class A { m(x, x) {} } var g = fn (y, y) {};"),
+            ["DuplicateParameter", "DuplicateParameter"]
+        );
+        assert!(errors("fn f(a, b, c) { var d = a; }").is_empty());
+    }
+
+    #[test]
+    fn scopes_end_when_resolving_inside_them_fails() {
+        // Each later redeclaration is a legal global one, so only the first error is reported
+        for source in [
+            "{ break; }",
+            "switch (1) { case 1: break 1; default: {} }",
+            "switch (1) { default: continue; }",
+            "match (1) { Number n => continue; else => {} }",
+            "match (1) { else => continue; }",
+            "for (x in []) { fn f() { continue; } }",
+            "class A { m() { break; } }",
+            "class A {} class B < A { m() { continue; } }",
+        ] {
+            let source = format!("{source}\nvar a = 1;\nvar a = 2;");
+            assert_eq!(errors(&source).len(), 1, "{source}");
+        }
+
+        // `this` is unbound again after the class that failed
+        assert_eq!(
+            errors("class A { m() { break; } }\nprint this;"),
+            ["BreakOutsideLoop", "ThisOutsideClass"]
+        );
+    }
+}
@@ -7,13 +7,13 @@ use super::token_type::{TokenType, KEYWORDS};
 #[derive(Debug, Error)]
 pub enum Error {
     #[error("[line {line}] Invalid UTF-8 character")]
-    InvalidUtf8Char { line: usize },
+    InvalidUtf8Char { line: usize, column: usize },
 
     #[error("[line {line}] Unexpected character `{c}`")]
-    UnexpectedCharacter { c: char, line: usize },
+    UnexpectedCharacter { c: char, line: usize, column: usize },
 
     #[error("[line {line}] Unterminated string")]
-    UnterminatedString { line: usize },
+    UnterminatedString { line: usize, column: usize },
 
     #[error(transparent)]
     NumberParse(#[from] std::num::ParseFloatError),
@@ -21,11 +21,56 @@ pub enum Error {
 
 type Result<T, E = Error> = std::result::Result<T, E>;
 
+impl Error {
+    /// The name of the variant, e.g. `UnterminatedString`.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::InvalidUtf8Char { .. } => "InvalidUtf8Char",
+            Self::UnexpectedCharacter { .. } => "UnexpectedCharacter",
+            Self::UnterminatedString { .. } => "UnterminatedString",
+            Self::NumberParse(_) => "NumberParse",
+        }
+    }
+
+    pub fn line(&self) -> Option<usize> {
+        match self {
+            Self::InvalidUtf8Char { line, .. }
+            | Self::UnexpectedCharacter { line, .. }
+            | Self::UnterminatedString { line, .. } => Some(*line),
+            Self::NumberParse(_) => None,
+        }
+    }
+
+    pub fn column(&self) -> Option<usize> {
+        match self {
+            Self::InvalidUtf8Char { column, .. }
+            | Self::UnexpectedCharacter { column, .. }
+            | Self::UnterminatedString { column, .. } => Some(*column),
+            Self::NumberParse(_) => None,
+        }
+    }
+}
+
 pub struct Scanner {
     source: Vec<u8>,
     start: usize,
     current: usize,
     line: usize,
+    /// The offset at which the current line begins
+    line_start: usize,
+    /// The column at which the current lexeme begins
+    column: usize,
+    finished: bool,
+    /// The comments scanned so far, which aren't turned into tokens
+    comments: Vec<Comment>,
+}
+
+/// A `//` comment, kept for tools like the formatter that write the source back out.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Comment {
+    pub line: usize,
+    /// The text after the `//`, without the line ending
+    pub text: String,
 }
 
 impl Scanner {
@@ -35,36 +80,96 @@ impl Scanner {
             start: 0,
             current: 0,
             line: 1,
+            line_start: 0,
+            column: 1,
+            finished: false,
+            comments: Vec::new(),
         }
     }
 
+    /// Takes the comments scanned so far, in the order they appear, for tools that would
+    /// otherwise lose them.
+    pub fn take_comments(&mut self) -> Vec<Comment> {
+        std::mem::take(&mut self.comments)
+    }
+
+    /// Scans the whole source up front, collecting every token or every error.
     pub fn scan_tokens(&mut self) -> Result<Vec<Token>, Vec<Error>> {
         let mut tokens = Vec::new();
         let mut errors = Vec::new();
 
+        while let Some(result) = self.next_token() {
+            match result {
+                Ok(token) => tokens.push(token),
+                Err(error) => errors.push(error),
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(tokens)
+        } else {
+            Err(errors)
+        }
+    }
+
+    /// Scans the next token, ending with an EOF token and then `None`.
+    pub fn next_token(&mut self) -> Option<Result<Token>> {
         while !self.is_at_end() {
             // We are at the beginning of the next lexeme
             self.start = self.current;
+            self.column = self.column_at(self.start);
             match self
                 .scan_token()
                 .and_then(|ov| ov.map(|t| self.make_token(t)).transpose())
             {
-                Ok(Some(token)) => tokens.push(token),
+                Ok(Some(token)) => return Some(Ok(token)),
                 Ok(None) => {}
-                Err(error) => errors.push(error),
+                Err(error) => return Some(Err(error)),
             }
         }
 
-        // Add the EOF token
-        tokens.push(Token::new(TokenType::Eof, String::new(), self.line));
-
-        if errors.is_empty() {
-            Ok(tokens)
+        if self.finished {
+            None
         } else {
-            Err(errors)
+            // Add the EOF token
+            self.finished = true;
+            let column = self.column_at(self.current);
+            Some(Ok(Token::new(
+                TokenType::Eof,
+                String::new(),
+                self.line,
+                column,
+            )))
         }
     }
 
+    /// The character column of an offset on the current line, counting from 1.
+    fn column_at(&self, offset: usize) -> usize {
+        // Skip UTF-8 continuation bytes so multibyte characters count once
+        let line = &self.source[self.line_start..offset];
+        line.iter().filter(|&&b| b & 0xC0 != 0x80).count() + 1
+    }
+
+    /// The line and character column of an offset before the current one, which unlike
+    /// `column_at` may be on an earlier line, e.g. inside a multiline string.
+    fn position_at(&self, offset: usize) -> (usize, usize) {
+        let lines_after = (self.source[offset..self.current].iter())
+            .filter(|&&b| b == b'\n')
+            .count();
+        let line_start = (self.source[..offset].iter())
+            .rposition(|&b| b == b'\n')
+            .map_or(0, |newline| newline + 1);
+        let column = (self.source[line_start..offset].iter())
+            .filter(|&&b| b & 0xC0 != 0x80)
+            .count();
+        (self.line - lines_after, column + 1)
+    }
+
+    fn new_line(&mut self) {
+        self.line += 1;
+        self.line_start = self.current;
+    }
+
     fn is_at_end(&self) -> bool {
         self.current >= self.source.len()
     }
@@ -76,14 +181,27 @@ impl Scanner {
             b')' => Ok(Some(TokenType::RightParen)),
             b'{' => Ok(Some(TokenType::LeftBrace)),
             b'}' => Ok(Some(TokenType::RightBrace)),
+            b'[' => Ok(Some(TokenType::LeftBracket)),
+            b']' => Ok(Some(TokenType::RightBracket)),
             b',' => Ok(Some(TokenType::Comma)),
             b'.' => Ok(Some(TokenType::Dot)),
             b'-' => Ok(Some(TokenType::Minus)),
             b'+' => Ok(Some(TokenType::Plus)),
             b';' => Ok(Some(TokenType::Semicolon)),
-            b'*' => Ok(Some(TokenType::Star)),
+            b'?' => Ok(Some(TokenType::Question)),
+            b':' => Ok(Some(TokenType::Colon)),
+            b'&' => Ok(Some(TokenType::Ampersand)),
+            b'|' => Ok(Some(TokenType::Pipe)),
+            b'^' => Ok(Some(TokenType::Caret)),
+            b'~' => Ok(Some(TokenType::Tilde)),
 
             // One or two character operators
+            b'*' => Ok(Some(if self.match_char(b'*') {
+                TokenType::StarStar
+            } else {
+                TokenType::Star
+            })),
+
             b'!' => Ok(Some(if self.match_char(b'=') {
                 TokenType::BangEqual
             } else {
@@ -92,18 +210,24 @@ impl Scanner {
 
             b'=' => Ok(Some(if self.match_char(b'=') {
                 TokenType::EqualEqual
+            } else if self.match_char(b'>') {
+                TokenType::FatArrow
             } else {
                 TokenType::Equal
             })),
 
             b'<' => Ok(Some(if self.match_char(b'=') {
                 TokenType::LessEqual
+            } else if self.match_char(b'<') {
+                TokenType::LessLess
             } else {
                 TokenType::Less
             })),
 
             b'>' => Ok(Some(if self.match_char(b'=') {
                 TokenType::GreaterEqual
+            } else if self.match_char(b'>') {
+                TokenType::GreaterGreater
             } else {
                 TokenType::Greater
             })),
@@ -114,6 +238,11 @@ impl Scanner {
                     while self.peek() != b'\n' && !self.is_at_end() {
                         self.advance();
                     }
+                    let text = String::from_utf8_lossy(&self.source[self.start + 2..self.current]);
+                    self.comments.push(Comment {
+                        line: self.line,
+                        text: text.trim_end().to_string(),
+                    });
                     Ok(None)
                 } else {
                     Ok(Some(TokenType::Slash))
@@ -123,7 +252,17 @@ impl Scanner {
             // Ignore whitespace
             b' ' | b'\r' | b'\t' => Ok(None),
             b'\n' => {
-                self.line += 1;
+                self.new_line();
+                Ok(None)
+            }
+
+            // A backslash at the end of a line continues it onto the next, like whitespace
+            b'\\'
+                if self.peek() == b'\n' || (self.peek() == b'\r' && self.peek_next() == b'\n') =>
+            {
+                self.match_char(b'\r');
+                self.advance();
+                self.new_line();
                 Ok(None)
             }
 
@@ -140,6 +279,7 @@ impl Scanner {
             c => Err(Error::UnexpectedCharacter {
                 c: c as char,
                 line: self.line,
+                column: self.column,
             }),
         }
     }
@@ -151,7 +291,7 @@ impl Scanner {
 
     fn make_token(&mut self, token_type: TokenType) -> Result<Token> {
         let text = self.substring(self.start, self.current)?;
-        Ok(Token::new(token_type, text, self.line))
+        Ok(Token::new(token_type, text, self.line, self.column))
     }
 
     fn match_char(&mut self, expected: u8) -> bool {
@@ -177,21 +317,28 @@ impl Scanner {
     }
 
     fn substring(&self, start: usize, end: usize) -> Result<String> {
-        String::from_utf8(self.source[start..end].to_vec())
-            .map_err(|_| Error::InvalidUtf8Char { line: self.line })
+        String::from_utf8(self.source[start..end].to_vec()).map_err(|e| {
+            let (line, column) = self.position_at(start + e.utf8_error().valid_up_to());
+            Error::InvalidUtf8Char { line, column }
+        })
     }
 
     fn string(&mut self) -> Result<TokenType> {
+        // Report an unterminated string where it opens, rather than at the end of the file
+        let (start_line, start_column) = (self.line, self.column);
+
         // Seek to the end of the string
         while self.peek() != b'"' && !self.is_at_end() {
-            if self.peek() == b'\n' {
-                self.line += 1;
+            if self.advance() == b'\n' {
+                self.new_line();
             }
-            self.advance();
         }
 
         if self.is_at_end() {
-            return Err(Error::UnterminatedString { line: self.line });
+            return Err(Error::UnterminatedString {
+                line: start_line,
+                column: start_column,
+            });
         }
 
         // Consume the closing quote
@@ -238,3 +385,97 @@ impl Scanner {
         }
     }
 }
+
+impl Iterator for Scanner {
+    type Item = Result<Token>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_token()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Comment, Scanner, Token};
+
+    const SOURCE: &str = r#"
+        // A comment, skipped by both
+        class Point < Base {
+            init(x, y) { this.x = x; this.y = y; }
+            norm { return sqrt(this.x * this.x + this.y * this.y); }
+        }
+        var label = "multi
+line";
+        print [1.5, 2 >= 3, nil != true] \
+            + label;
+    "#;
+
+    #[test]
+    fn streaming_matches_batch_scanning() {
+        let batch = Scanner::new(SOURCE.into()).scan_tokens().unwrap();
+        let streamed = Scanner::new(SOURCE.into())
+            .collect::<Result<Vec<Token>, _>>()
+            .unwrap();
+        assert_eq!(streamed, batch);
+    }
+
+    #[test]
+    fn streaming_continues_after_an_error() {
+        let results = Scanner::new(b"1 @ 2".to_vec()).collect::<Vec<_>>();
+        assert_eq!(results.len(), 4);
+        assert!(results[1].is_err());
+        assert_eq!(results.iter().filter(|result| result.is_ok()).count(), 3);
+    }
+
+    #[test]
+    fn string_errors_point_at_their_column() {
+        let errors = Scanner::new(b"print \"a\nb\xFF\";\nx = \"open".to_vec())
+            .scan_tokens()
+            .unwrap_err();
+        assert_eq!(
+            errors
+                .iter()
+                .map(|e| (e.kind(), e.line(), e.column()))
+                .collect::<Vec<_>>(),
+            [
+                ("InvalidUtf8Char", Some(2), Some(2)),
+                ("UnterminatedString", Some(3), Some(5)),
+            ]
+        );
+    }
+
+    #[test]
+    fn backslashes_continue_lines() {
+        let tokens = Scanner::new(b"a \\\n  + \\\r\nb".to_vec())
+            .scan_tokens()
+            .unwrap();
+        assert_eq!(
+            (tokens.iter())
+                .map(|t| (t.get_lexeme(), t.get_line()))
+                .collect::<Vec<_>>(),
+            [
+                (String::from("a"), 1),
+                (String::from("+"), 2),
+                (String::from("b"), 3),
+                (String::new(), 3),
+            ]
+        );
+
+        let errors = Scanner::new(b"a \\ b".to_vec()).scan_tokens().unwrap_err();
+        assert_eq!(errors[0].to_string(), "[line 1] Unexpected character `\\`");
+    }
+
+    #[test]
+    fn comments_are_kept_with_their_lines() {
+        let mut scanner = Scanner::new(b"// first\r\nprint 1; //second  \n//".to_vec());
+        assert_eq!(scanner.scan_tokens().unwrap().len(), 4);
+        let comment = |line, text: &str| Comment {
+            line,
+            text: text.to_string(),
+        };
+        assert_eq!(
+            scanner.take_comments(),
+            [comment(1, " first"), comment(2, "second"), comment(3, "")]
+        );
+    }
+}
@@ -0,0 +1,57 @@
+use std::{cell::RefCell, collections::HashMap, fmt, rc::Rc};
+
+use serde::{Serialize, Serializer};
+
+/// An interned string, so names can be copied, compared, and hashed without touching the text.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Symbol(u32);
+
+#[derive(Default)]
+struct Interner {
+    strings: Vec<Rc<str>>,
+    symbols: HashMap<Rc<str>, Symbol>,
+}
+
+thread_local! {
+    static INTERNER: RefCell<Interner> = RefCell::new(Interner::default());
+}
+
+impl Symbol {
+    /// Returns the symbol for a string, interning it the first time it's seen.
+    pub fn intern(string: &str) -> Self {
+        INTERNER.with(|interner| {
+            let mut interner = interner.borrow_mut();
+            if let Some(&symbol) = interner.symbols.get(string) {
+                return symbol;
+            }
+
+            let symbol = Symbol(interner.strings.len() as u32);
+            let string: Rc<str> = Rc::from(string);
+            interner.strings.push(string.clone());
+            interner.symbols.insert(string, symbol);
+            symbol
+        })
+    }
+
+    pub fn as_str(&self) -> Rc<str> {
+        INTERNER.with(|interner| interner.borrow().strings[self.0 as usize].clone())
+    }
+}
+
+impl fmt::Display for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+impl fmt::Debug for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", self.as_str())
+    }
+}
+
+impl Serialize for Symbol {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.as_str())
+    }
+}
@@ -0,0 +1,55 @@
+//! Helpers for running Lox source in tests and capturing what it prints.
+
+use std::{cell::RefCell, io::Write, rc::Rc};
+
+use super::{Error, Loxide};
+
+/// A `print` destination that can still be read after it's handed to the interpreter.
+#[derive(Clone, Default)]
+pub struct Output(Rc<RefCell<Vec<u8>>>);
+
+impl Output {
+    pub fn text(&self) -> String {
+        String::from_utf8(self.0.borrow().clone()).expect("Output isn't UTF-8")
+    }
+}
+
+impl Write for Output {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.borrow_mut().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Creates an interpreter whose printed output is captured, for running several sources
+/// in one session like the REPL does.
+pub fn session() -> (Loxide, Output) {
+    let mut loxide = Loxide::new();
+    let output = Output::default();
+    loxide.set_output(Box::new(output.clone()));
+    (loxide, output)
+}
+
+/// Runs a program in a fresh interpreter, returning what it printed.
+pub fn run(source: &str) -> Result<String, Error> {
+    let (mut loxide, output) = session();
+    loxide.eval(source)?;
+    Ok(output.text())
+}
+
+/// Runs a program that's expected to succeed, returning what it printed.
+pub fn output(source: &str) -> String {
+    run(source).unwrap_or_else(|e| panic!("Expected {source:?} to run, but got: {e}"))
+}
+
+/// Runs a program that's expected to fail, returning the error.
+pub fn error(source: &str) -> Error {
+    match run(source) {
+        Ok(output) => panic!("Expected {source:?} to fail, but it printed: {output:?}"),
+        Err(e) => e,
+    }
+}
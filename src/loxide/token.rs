@@ -1,20 +1,25 @@
 use std::fmt;
 
-use super::token_type::TokenType;
+use serde::Serialize;
 
-#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+use super::{symbol::Symbol, token_type::TokenType};
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize)]
 pub struct Token {
     token_type: TokenType,
-    lexeme: String,
+    lexeme: Symbol,
     line: usize,
+    /// The character column of the start of the lexeme, counting from 1.
+    column: usize,
 }
 
 impl Token {
-    pub fn new(token_type: TokenType, lexeme: String, line: usize) -> Self {
+    pub fn new(token_type: TokenType, lexeme: String, line: usize, column: usize) -> Self {
         Self {
             token_type,
-            lexeme,
+            lexeme: Symbol::intern(&lexeme),
             line,
+            column,
         }
     }
 
@@ -26,8 +31,17 @@ impl Token {
         self.line
     }
 
+    pub fn get_column(&self) -> usize {
+        self.column
+    }
+
     pub fn get_lexeme(&self) -> String {
-        self.lexeme.clone()
+        self.lexeme.to_string()
+    }
+
+    /// The interned lexeme, which is cheap to copy, compare, and hash.
+    pub fn get_symbol(&self) -> Symbol {
+        self.lexeme
     }
 }
 
@@ -35,8 +49,8 @@ impl fmt::Display for Token {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(
             f,
-            "Type: {:?} | Lexeme: {} | Line: {}",
-            self.token_type, self.lexeme, self.line
+            "Type: {:?} | Lexeme: {} | Line: {} | Column: {}",
+            self.token_type, self.lexeme, self.line, self.column
         )
     }
 }
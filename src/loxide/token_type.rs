@@ -2,14 +2,17 @@ use std::{collections::HashMap, fmt};
 
 use lazy_static::lazy_static;
 use ordered_float::OrderedFloat;
+use serde::Serialize;
 
-#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize)]
 pub enum TokenType {
     // Single-character tokens
     LeftParen,
     RightParen,
     LeftBrace,
     RightBrace,
+    LeftBracket,
+    RightBracket,
     Comma,
     Dot,
     Minus,
@@ -17,15 +20,25 @@ pub enum TokenType {
     Semicolon,
     Slash,
     Star,
+    Question,
+    Colon,
+    Ampersand,
+    Pipe,
+    Caret,
+    Tilde,
     // One or two character tokens
+    StarStar,
     Bang,
     BangEqual,
     Equal,
     EqualEqual,
+    FatArrow,
     Greater,
     GreaterEqual,
     Less,
     LessEqual,
+    LessLess,
+    GreaterGreater,
     // Literals
     Identifier(String),
     String(String),
@@ -33,17 +46,27 @@ pub enum TokenType {
     // Keywords
     And,
     Break,
+    Case,
     Class,
+    Const,
+    Continue,
+    Default,
+    /// Floored division, e.g. `7 div 2` is 3. A keyword, since `//` starts a comment.
+    Div,
     Else,
     False,
     For,
     Fn,
     If,
+    In,
+    Loop,
+    Match,
     Nil,
     Or,
     Print,
     Return,
     Super,
+    Switch,
     This,
     True,
     Var,
@@ -57,17 +80,26 @@ lazy_static! {
         let mut m = HashMap::new();
         m.insert("and".to_string(), TokenType::And);
         m.insert("break".to_string(), TokenType::Break);
+        m.insert("case".to_string(), TokenType::Case);
         m.insert("class".to_string(), TokenType::Class);
+        m.insert("const".to_string(), TokenType::Const);
+        m.insert("continue".to_string(), TokenType::Continue);
+        m.insert("default".to_string(), TokenType::Default);
+        m.insert("div".to_string(), TokenType::Div);
         m.insert("else".to_string(), TokenType::Else);
         m.insert("false".to_string(), TokenType::False);
         m.insert("for".to_string(), TokenType::For);
         m.insert("fn".to_string(), TokenType::Fn);
         m.insert("if".to_string(), TokenType::If);
+        m.insert("in".to_string(), TokenType::In);
+        m.insert("loop".to_string(), TokenType::Loop);
+        m.insert("match".to_string(), TokenType::Match);
         m.insert("nil".to_string(), TokenType::Nil);
         m.insert("or".to_string(), TokenType::Or);
         m.insert("print".to_string(), TokenType::Print);
         m.insert("return".to_string(), TokenType::Return);
         m.insert("super".to_string(), TokenType::Super);
+        m.insert("switch".to_string(), TokenType::Switch);
         m.insert("this".to_string(), TokenType::This);
         m.insert("true".to_string(), TokenType::True);
         m.insert("var".to_string(), TokenType::Var);
@@ -83,6 +115,8 @@ impl fmt::Display for TokenType {
             Self::RightParen => write!(f, ")"),
             Self::LeftBrace => write!(f, "{{"),
             Self::RightBrace => write!(f, "}}"),
+            Self::LeftBracket => write!(f, "["),
+            Self::RightBracket => write!(f, "]"),
             Self::Comma => write!(f, ","),
             Self::Dot => write!(f, "."),
             Self::Minus => write!(f, "-"),
@@ -90,29 +124,48 @@ impl fmt::Display for TokenType {
             Self::Semicolon => write!(f, ";"),
             Self::Slash => write!(f, "/"),
             Self::Star => write!(f, "*"),
+            Self::StarStar => write!(f, "**"),
+            Self::Question => write!(f, "?"),
+            Self::Colon => write!(f, ":"),
+            Self::Ampersand => write!(f, "&"),
+            Self::Pipe => write!(f, "|"),
+            Self::Caret => write!(f, "^"),
+            Self::Tilde => write!(f, "~"),
             Self::Bang => write!(f, "!"),
             Self::BangEqual => write!(f, "!="),
             Self::Equal => write!(f, "="),
             Self::EqualEqual => write!(f, "=="),
+            Self::FatArrow => write!(f, "=>"),
             Self::Greater => write!(f, ">"),
             Self::GreaterEqual => write!(f, ">="),
             Self::Less => write!(f, "<"),
             Self::LessEqual => write!(f, "<="),
+            Self::LessLess => write!(f, "<<"),
+            Self::GreaterGreater => write!(f, ">>"),
             Self::Identifier(s) | Self::String(s) => write!(f, "{s}"),
             Self::Number(n) => write!(f, "{n}"),
             Self::And => write!(f, "and"),
             Self::Break => write!(f, "break"),
+            Self::Case => write!(f, "case"),
             Self::Class => write!(f, "class"),
+            Self::Const => write!(f, "const"),
+            Self::Continue => write!(f, "continue"),
+            Self::Default => write!(f, "default"),
+            Self::Div => write!(f, "div"),
             Self::Else => write!(f, "else"),
             Self::False => write!(f, "false"),
             Self::For => write!(f, "for"),
             Self::Fn => write!(f, "fn"),
             Self::If => write!(f, "if"),
+            Self::In => write!(f, "in"),
+            Self::Loop => write!(f, "loop"),
+            Self::Match => write!(f, "match"),
             Self::Nil => write!(f, "nil"),
             Self::Or => write!(f, "or"),
             Self::Print => write!(f, "print"),
             Self::Return => write!(f, "return"),
             Self::Super => write!(f, "super"),
+            Self::Switch => write!(f, "switch"),
             Self::This => write!(f, "this"),
             Self::True => write!(f, "true"),
             Self::Var => write!(f, "var"),
@@ -1,25 +1,71 @@
-use loxide::{Error, Loxide};
+use std::io::Read;
 
-mod loxide;
+use loxide::{Error, Loxide};
 
 fn main() {
     let args = std::env::args().collect::<Vec<String>>();
     let mut loxide = Loxide::new();
-    match args.len() {
-        1 => loxide.run_repl().unwrap(),
-        2 => {
-            if let Err(e) = loxide.run_file(&args[1]) {
-                println!("{e}");
-                std::process::exit(match e {
-                    Error::Runtime(_) => 70,
-                    Error::Io(_) => 74,
-                    _ => 65,
-                });
-            }
+    let (flag, path) = match args.as_slice() {
+        [_] => {
+            loxide.run_repl().unwrap();
+            return;
         }
+        [_, flag, path]
+            if ["--tokens", "--ast", "--fmt", "--time", "--strict"].contains(&flag.as_str()) =>
+        {
+            (Some(flag.as_str()), path)
+        }
+        [_, path] if !path.starts_with("--") => (None, path),
         _ => {
-            println!("Usage: loxide [script]");
+            println!("Usage: loxide [--tokens | --ast | --fmt | --time | --strict] [script | -]");
             std::process::exit(64);
         }
+    };
+
+    // The script is read once, and errors are shown against the same source that was run
+    let source = match read_script(path) {
+        Ok(source) => source,
+        Err(e) => {
+            println!("{}", Error::Io(e));
+            std::process::exit(74);
+        }
+    };
+
+    let result = match flag {
+        Some("--tokens") => {
+            (loxide.scan(&source)).map(|tokens| tokens.iter().for_each(|token| println!("{token}")))
+        }
+        Some("--ast") => loxide.dump_ast(&source).map(|ast| print!("{ast}")),
+        Some("--fmt") => loxide.format(&source).map(|source| print!("{source}")),
+        Some("--time") => {
+            loxide.set_timings(true);
+            loxide.run_script(&source)
+        }
+        Some("--strict") => {
+            loxide.set_warnings(true);
+            loxide.run_script(&source)
+        }
+        _ => loxide.run_script(&source),
+    };
+
+    if let Err(e) = result {
+        println!("{}", e.render(&String::from_utf8_lossy(&source)));
+        std::process::exit(match e {
+            Error::Runtime(_) => 70,
+            Error::Io(_) => 74,
+            _ => 65,
+        });
+    }
+}
+
+/// Reads the whole script from the file at `path`, or from stdin until EOF for `-`.
+fn read_script(path: &str) -> std::io::Result<Vec<u8>> {
+    match path {
+        "-" => {
+            let mut source = Vec::new();
+            std::io::stdin().read_to_end(&mut source)?;
+            Ok(source)
+        }
+        path => std::fs::read(path),
     }
 }
@@ -0,0 +1,59 @@
+use std::{
+    io::Write,
+    process::{Command, Output, Stdio},
+};
+
+/// Runs the interpreter with `args`, feeding `source` to it on stdin.
+fn loxide(args: &[&str], source: &str) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_loxide"))
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("Failed to start loxide");
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(source.as_bytes())
+        .unwrap();
+    child.wait_with_output().unwrap()
+}
+
+/// The phases named in the `--time` summary, in the order they're printed.
+fn phases(output: &Output) -> Vec<String> {
+    String::from_utf8_lossy(&output.stderr)
+        .lines()
+        .filter_map(|line| line.split_once(':').map(|(phase, _)| phase.to_string()))
+        .collect()
+}
+
+#[test]
+fn time_reports_each_phase_on_stderr() {
+    let run = loxide(&["--time", "-"], "print 1 + 2;");
+    assert!(run.status.success());
+    assert_eq!(String::from_utf8_lossy(&run.stdout), "3\n");
+    assert_eq!(phases(&run), ["scan", "parse", "resolve", "interpret"]);
+
+    // Without the flag, nothing is timed
+    let run = loxide(&["-"], "print 1 + 2;");
+    assert!(run.stderr.is_empty());
+}
+
+#[test]
+fn time_reports_phases_that_ran_before_an_error() {
+    // A runtime error still has every phase timed
+    let run = loxide(&["--time", "-"], "print nil + 1;");
+    assert_eq!(run.status.code(), Some(70));
+    assert_eq!(phases(&run), ["scan", "parse", "resolve", "interpret"]);
+
+    // A syntax error stops before anything is timed
+    let run = loxide(&["--time", "-"], "print ;");
+    assert_eq!(run.status.code(), Some(65));
+    assert!(phases(&run).is_empty());
+
+    // The flag needs a script to time
+    let run = loxide(&["--time"], "");
+    assert_eq!(run.status.code(), Some(64));
+}